@@ -0,0 +1,63 @@
+//! Turning arbitrary CBOR into DAG-CBOR.
+//!
+//! The input is decoded with [`DeserializerOptions::lenient`] and encoded again, which results in
+//! valid DAG-CBOR:
+//!  - map keys are sorted by length first and bytewise second, like all maps are when encoding
+//!  - integers, lengths and tags are encoded with the minimal number of bytes
+//!  - half and single-precision floats are widened to double-precision
+//!  - items of indefinite length are converted to items of definite length
+//!
+//! Data that can't be represented in DAG-CBOR is rejected, e.g. map keys that aren't strings,
+//! tags other than 42, NaN or duplicate map keys.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_ipld_dagcbor::canonical;
+//!
+//! // {"b": 1, "a": 24 encoded in two bytes}
+//! let (bytes, deviations) = canonical::canonicalize(b"\xa2\x61b\x01\x61a\x19\x00\x18").unwrap();
+//! assert_eq!(bytes, b"\xa2\x61a\x18\x18\x61b\x01");
+//! assert!(!deviations.is_empty());
+//!
+//! // The result is canonical already.
+//! let (_, deviations) = canonical::canonicalize(&bytes).unwrap();
+//! assert!(deviations.is_empty());
+//! ```
+use alloc::vec::Vec;
+
+use ipld_core::ipld::Ipld;
+
+use crate::de::{Deserializer, DeserializerOptions, Deviations};
+use crate::error::CodecError;
+
+/// Converts a single CBOR value into DAG-CBOR.
+///
+/// Besides the DAG-CBOR, the deviations of the input from DAG-CBOR are returned. If there are
+/// none, the input was canonical already and the output is byte-identical to the input.
+pub fn canonicalize(input: &[u8]) -> Result<(Vec<u8>, Deviations), CodecError> {
+    let mut deserializer =
+        Deserializer::from_slice(input).with_options(DeserializerOptions::lenient());
+    let ipld: Ipld = serde::Deserialize::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    let bytes = crate::to_vec(&ipld)?;
+    Ok((bytes, deserializer.deviations()))
+}
+
+/// Converts a single CBOR value from a reader into DAG-CBOR and writes it to a writer.
+///
+/// The whole reader is consumed, trailing data is an error. The returned deviations are empty if
+/// the input was canonical already. See [`canonicalize`] for details.
+#[cfg(feature = "std")]
+pub fn canonicalize_reader<R, W>(reader: R, writer: W) -> Result<Deviations, CodecError>
+where
+    R: std::io::BufRead,
+    W: std::io::Write,
+{
+    let mut deserializer = Deserializer::from_reader(cbor4ii::core::utils::IoReader::new(reader))
+        .with_options(DeserializerOptions::lenient());
+    let ipld: Ipld = serde::Deserialize::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    crate::to_writer(writer, &ipld)?;
+    Ok(deserializer.deviations())
+}
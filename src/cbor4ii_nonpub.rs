@@ -1,10 +1,13 @@
-//! Re-implementations of cbor4ii's `pub(crate)` `peek_one`/`pull_one` so we can produce our own
+//! Re-implementations of cbor4ii's `pub(crate)` `peek_one` so we can produce our own
 //! `DecodeError::Eof` instead of going through the upstream constructor.
+//!
+//! It also contains a re-implementation of the private `TypeNum::decode_u64`, which additionally
+//! returns the initial byte, so that the encoding of the argument can be inspected.
 
 use cbor4ii::core::dec;
 use cbor4ii::core::error::Len;
 
-use crate::error::DecodeError;
+use crate::error::{DecodeError, Location};
 
 #[inline]
 pub(crate) fn peek_one<'a, R: dec::Read<'a>>(
@@ -15,16 +18,85 @@ pub(crate) fn peek_one<'a, R: dec::Read<'a>>(
     let byte = buf.as_ref().first().copied().ok_or(DecodeError::Eof {
         name,
         expect: Len::Small(1),
+        location: Location::default(),
     })?;
     Ok(byte)
 }
 
 #[inline]
-pub(crate) fn pull_one<'a, R: dec::Read<'a>>(
+pub(crate) fn pull_exact<'a, R: dec::Read<'a>>(
     name: &'static str,
     reader: &mut R,
-) -> Result<u8, DecodeError<R::Error>> {
+    mut buf: &mut [u8],
+) -> Result<(), DecodeError<R::Error>> {
+    let buf_len = buf.len();
+    while !buf.is_empty() {
+        let readbuf = reader.fill(buf.len())?;
+        let readbuf = readbuf.as_ref();
+        if readbuf.is_empty() {
+            return Err(DecodeError::Eof {
+                name,
+                expect: Len::new(buf_len),
+                location: Location::default(),
+            });
+        }
+        let len = core::cmp::min(buf.len(), readbuf.len());
+        buf[..len].copy_from_slice(&readbuf[..len]);
+        reader.advance(len);
+        buf = &mut buf[len..];
+    }
+    Ok(())
+}
+
+/// Decodes the head of a data item of the given major type.
+///
+/// Returns the initial byte together with the argument. Indefinite lengths are not handled here,
+/// they need to be checked for before calling this function.
+#[inline]
+pub(crate) fn pull_head<'a, R: dec::Read<'a>>(
+    name: &'static str,
+    major: u8,
+    reader: &mut R,
+) -> Result<(u8, u64), DecodeError<R::Error>> {
     let byte = peek_one(name, reader)?;
+    if dec::if_major(byte) != major {
+        return Err(DecodeError::Mismatch {
+            name,
+            found: byte,
+            location: Location::default(),
+        });
+    }
+    let mut buf = [0; 8];
+    let n = match dec::low(byte) {
+        info @ 0..=0x17 => {
+            reader.advance(1);
+            return Ok((byte, info.into()));
+        }
+        0x18 => 8 - 1,
+        0x19 => 8 - 2,
+        0x1a => 8 - 4,
+        0x1b => 0,
+        _ => {
+            return Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            })
+        }
+    };
     reader.advance(1);
-    Ok(byte)
+    pull_exact(name, reader, &mut buf[n..])?;
+    Ok((byte, u64::from_be_bytes(buf)))
+}
+
+/// Returns whether the argument of a head is encoded with the minimal number of bytes.
+#[inline]
+pub(crate) fn is_minimal(byte: u8, value: u64) -> bool {
+    match dec::low(byte) {
+        0x18 => value >= 0x18,
+        0x19 => value > u64::from(u8::MAX),
+        0x1a => value > u64::from(u16::MAX),
+        0x1b => value > u64::from(u32::MAX),
+        _ => true,
+    }
 }
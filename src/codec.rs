@@ -5,11 +5,10 @@ use std::io::{BufRead, Write};
 use ipld_core::{
     cid::Cid,
     codec::{Codec, Links},
-    serde::ExtractLinks,
 };
 use serde::{de::Deserialize, ser::Serialize};
 
-use crate::{de::Deserializer, error::CodecError, DAG_CBOR_CODE};
+use crate::{error::CodecError, links::LinkScanner, DAG_CBOR_CODE};
 
 /// DAG-CBOR implementation of ipld-core's `Codec` trait.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -35,9 +34,9 @@ impl Links for DagCborCodec {
     type LinksError = CodecError;
 
     fn links(data: &[u8]) -> Result<impl Iterator<Item = Cid>, Self::LinksError> {
-        let mut deserializer = Deserializer::from_slice(data);
-        Ok(ExtractLinks::deserialize(&mut deserializer)?
-            .into_vec()
-            .into_iter())
+        // Scanning the bytes doesn't depend on how CIDs are passed through Serde, hence it also
+        // works with the `no-cid-as-bytes` feature.
+        let links = LinkScanner::from_slice(data).collect::<Result<Vec<_>, _>>()?;
+        Ok(links.into_iter())
     }
 }
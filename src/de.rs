@@ -1,19 +1,24 @@
 //! Deserialization.
 #[cfg(not(feature = "std"))]
-use alloc::borrow::Cow;
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::cmp;
 use core::convert::{Infallible, TryFrom};
+use core::fmt;
 use core::marker::PhantomData;
 use serde::Deserialize;
 #[cfg(feature = "std")]
 use std::borrow::Cow;
 
-use cbor4ii::core::dec::{self, Decode};
-use cbor4ii::core::{major, marker, types, utils::SliceReader};
-use ipld_core::cid::serde::CID_SERDE_PRIVATE_IDENTIFIER;
-use serde::de::{self, Visitor};
+use cbor4ii::core::dec::{self, Decode, Read as _};
+use cbor4ii::core::error::Len;
+use cbor4ii::core::{major, marker, utils::SliceReader};
+use ipld_core::cid::serde::{BytesToCidVisitor, CID_SERDE_PRIVATE_IDENTIFIER};
+use ipld_core::cid::Cid;
+use serde::de::value::{BorrowedStrDeserializer, StringDeserializer};
+use serde::de::{self, IntoDeserializer, Visitor};
 
-use crate::cbor4ii_nonpub::{peek_one, pull_one};
-use crate::error::DecodeError;
+use crate::cbor4ii_nonpub::{is_minimal, peek_one, pull_exact, pull_head};
+use crate::error::{DecodeError, InvalidCidReason, Location, PathSegment};
 use crate::CBOR_TAGS_CID;
 #[cfg(feature = "std")]
 use cbor4ii::core::utils::IoReader;
@@ -43,13 +48,71 @@ pub fn from_slice<'a, T>(buf: &'a [u8]) -> Result<T, DecodeError<Infallible>>
 where
     T: de::Deserialize<'a>,
 {
-    let reader = SliceReader::new(buf);
-    let mut deserializer = Deserializer::from_reader(reader);
+    from_slice_with(buf, DeserializerOptions::default())
+}
+
+/// Decodes a value from CBOR data in a slice and rejects anything that isn't valid DAG-CBOR.
+///
+/// See [`DeserializerOptions::strict`] for the checks that are performed.
+///
+/// # Examples
+///
+/// ```
+/// # use serde_ipld_dagcbor::{de, DecodeError};
+/// // The number 5 has to be encoded within the initial byte, not with an additional byte.
+/// let value: u8 = de::from_slice_strict(&[0x05]).unwrap();
+/// assert_eq!(value, 5);
+/// let result = de::from_slice_strict::<u8>(&[0x18, 0x05]);
+/// assert!(matches!(result, Err(DecodeError::NonMinimalEncoding { .. })));
+/// ```
+pub fn from_slice_strict<'a, T>(buf: &'a [u8]) -> Result<T, DecodeError<Infallible>>
+where
+    T: de::Deserialize<'a>,
+{
+    from_slice_with(buf, DeserializerOptions::strict())
+}
+
+/// Decodes a value from CBOR data in a slice with the given options.
+pub fn from_slice_with<'a, T>(
+    buf: &'a [u8],
+    options: DeserializerOptions,
+) -> Result<T, DecodeError<Infallible>>
+where
+    T: de::Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_slice(buf).with_options(options);
     let value = serde::Deserialize::deserialize(&mut deserializer)?;
     deserializer.end()?;
     Ok(value)
 }
 
+/// Decodes a value from CBOR data in a slice that may deviate from DAG-CBOR.
+///
+/// The data is decoded with [`DeserializerOptions::lenient`]. Besides the value, the deviations
+/// from DAG-CBOR that were accepted are returned. Encoding the value again results in valid
+/// DAG-CBOR.
+///
+/// # Examples
+///
+/// ```
+/// use serde_ipld_dagcbor::de;
+///
+/// // An indefinite length list.
+/// let (value, deviations): (Vec<u8>, _) = de::from_slice_lenient(&[0x9f, 0x01, 0x02, 0xff]).unwrap();
+/// assert_eq!(value, [1, 2]);
+/// assert_eq!(deviations.indefinite_lengths, 1);
+/// ```
+pub fn from_slice_lenient<'a, T>(buf: &'a [u8]) -> Result<(T, Deviations), DecodeError<Infallible>>
+where
+    T: de::Deserialize<'a>,
+{
+    let mut deserializer =
+        Deserializer::from_slice(buf).with_options(DeserializerOptions::lenient());
+    let value = serde::Deserialize::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok((value, deserializer.deviations()))
+}
+
 /// Decodes a value from CBOR data in a reader.
 ///
 /// # Examples
@@ -73,17 +136,47 @@ where
 /// ```
 #[cfg(feature = "std")]
 pub fn from_reader<T, R>(reader: R) -> Result<T, DecodeError<std::io::Error>>
+where
+    T: de::DeserializeOwned,
+    R: std::io::BufRead,
+{
+    from_reader_with(reader, DeserializerOptions::default())
+}
+
+/// Decodes a value from CBOR data in a reader with the given options.
+#[cfg(feature = "std")]
+pub fn from_reader_with<T, R>(
+    reader: R,
+    options: DeserializerOptions,
+) -> Result<T, DecodeError<std::io::Error>>
 where
     T: de::DeserializeOwned,
     R: std::io::BufRead,
 {
     let reader = IoReader::new(reader);
-    let mut deserializer = Deserializer::from_reader(reader);
+    let mut deserializer = Deserializer::from_reader(reader).with_options(options);
     let value = serde::Deserialize::deserialize(&mut deserializer)?;
     deserializer.end()?;
     Ok(value)
 }
 
+/// Decodes a value from CBOR data in a reader that may deviate from DAG-CBOR.
+///
+/// See [`from_slice_lenient`] for details.
+#[cfg(feature = "std")]
+pub fn from_reader_lenient<T, R>(reader: R) -> Result<(T, Deviations), DecodeError<std::io::Error>>
+where
+    T: de::DeserializeOwned,
+    R: std::io::BufRead,
+{
+    let reader = IoReader::new(reader);
+    let mut deserializer =
+        Deserializer::from_reader(reader).with_options(DeserializerOptions::lenient());
+    let value = serde::Deserialize::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok((value, deserializer.deviations()))
+}
+
 /// Decodes a single value from CBOR data in a reader. If there are multiple
 /// concatenated values in the reader, this function will succeed. On success,
 /// it returns the decoded value. The reader will be left with all trailing
@@ -157,16 +250,320 @@ where
     Deserializer::from_reader(reader).into_iter()
 }
 
+/// The default maximum nesting depth, see [`DeserializerOptions::max_depth`].
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Options that control how strictly DAG-CBOR is decoded.
+///
+/// The default options accept some encodings that aren't valid DAG-CBOR, in order to stay
+/// compatible with data written by other encoders. Use [`DeserializerOptions::strict`] to reject
+/// those.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeserializerOptions {
+    require_minimal_encoding: bool,
+    require_canonical_map_keys: bool,
+    require_f64_floats: bool,
+    max_depth: usize,
+    max_string_len: usize,
+    max_collection_len: usize,
+    max_total_items: usize,
+    max_allocated_bytes: usize,
+    track_path: bool,
+    allow_indefinite_lengths: bool,
+    allow_non_minimal_tags: bool,
+    allow_f16_floats: bool,
+    record_deviations: bool,
+    validate_cids: bool,
+}
+
+impl Default for DeserializerOptions {
+    fn default() -> Self {
+        Self {
+            require_minimal_encoding: false,
+            require_canonical_map_keys: false,
+            require_f64_floats: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_string_len: usize::MAX,
+            max_collection_len: usize::MAX,
+            max_total_items: usize::MAX,
+            max_allocated_bytes: usize::MAX,
+            track_path: false,
+            allow_indefinite_lengths: false,
+            allow_non_minimal_tags: false,
+            allow_f16_floats: false,
+            record_deviations: false,
+            validate_cids: false,
+        }
+    }
+}
+
+impl DeserializerOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates options that only accept valid DAG-CBOR.
+    ///
+    /// The following checks are enabled:
+    ///  - [`require_minimal_encoding`](Self::require_minimal_encoding)
+    ///  - [`require_canonical_map_keys`](Self::require_canonical_map_keys)
+    ///  - [`require_f64_floats`](Self::require_f64_floats)
+    ///  - [`validate_cids`](Self::validate_cids)
+    pub fn strict() -> Self {
+        Self::new()
+            .require_minimal_encoding(true)
+            .require_canonical_map_keys(true)
+            .require_f64_floats(true)
+            .validate_cids(true)
+    }
+
+    /// Creates options that accept common deviations from DAG-CBOR and record them.
+    ///
+    /// This is meant for loading data written by older or non-DAG-CBOR encoders, in order to
+    /// encode it again canonically. On top of the default options, the following are enabled:
+    ///  - [`allow_indefinite_lengths`](Self::allow_indefinite_lengths)
+    ///  - [`allow_non_minimal_tags`](Self::allow_non_minimal_tags)
+    ///  - [`allow_f16_floats`](Self::allow_f16_floats)
+    ///  - [`record_deviations`](Self::record_deviations)
+    pub fn lenient() -> Self {
+        Self::new()
+            .allow_indefinite_lengths(true)
+            .allow_non_minimal_tags(true)
+            .allow_f16_floats(true)
+            .record_deviations(true)
+    }
+
+    /// Sets whether integers, tags and lengths must be encoded with the minimal number of bytes.
+    ///
+    /// If enabled, non-minimal encodings are rejected with [`DecodeError::NonMinimalEncoding`].
+    pub fn require_minimal_encoding(mut self, require: bool) -> Self {
+        self.require_minimal_encoding = require;
+        self
+    }
+
+    /// Sets whether map keys must be unique and sorted in canonical order.
+    ///
+    /// The canonical order sorts keys by the length of their encoding first and bytewise second.
+    /// If enabled, violations are rejected with [`DecodeError::UnsortedMapKeys`] or
+    /// [`DecodeError::DuplicateMapKey`].
+    pub fn require_canonical_map_keys(mut self, require: bool) -> Self {
+        self.require_canonical_map_keys = require;
+        self
+    }
+
+    /// Sets whether floats must be encoded with 64 bits.
+    ///
+    /// Half-precision floats are rejected, unless [`allow_f16_floats`](Self::allow_f16_floats) is
+    /// enabled. By default, single-precision floats are accepted for compatibility. If enabled,
+    /// they are rejected with [`DecodeError::Mismatch`], just like NaN and Infinity.
+    pub fn require_f64_floats(mut self, require: bool) -> Self {
+        self.require_f64_floats = require;
+        self
+    }
+
+    /// Sets the maximum nesting depth of the decoded data.
+    ///
+    /// Every array and map counts as one level, so a list of lists of integers has a depth of two.
+    /// As decoding them recurses as well, a value decoded into `Some` counts as one level too, so
+    /// do enums, even unit variants encoded as strings, and skipped tags. The default is 256.
+    /// Deeper data is rejected with [`DecodeError::DepthOverflow`]. Note that decoding is
+    /// recursive, so large limits need a correspondingly large stack.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the maximum length in bytes of a single string or byte string.
+    ///
+    /// Longer strings are rejected with [`DecodeError::StringTooLong`] before they are read. There
+    /// is no limit by default.
+    pub fn max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = max_string_len;
+        self
+    }
+
+    /// Sets the maximum number of elements of a single array or map.
+    ///
+    /// Larger collections are rejected with [`DecodeError::CollectionTooLong`] before their
+    /// elements are read. There is no limit by default.
+    pub fn max_collection_len(mut self, max_collection_len: usize) -> Self {
+        self.max_collection_len = max_collection_len;
+        self
+    }
+
+    /// Sets the maximum number of items within all arrays and maps of a value.
+    ///
+    /// Every array element counts as one item, every map entry as two. The count is based on the
+    /// encoded lengths, so that the limit is hit before the items are read. Exceeding it results
+    /// in [`DecodeError::TooManyItems`]. There is no limit by default.
+    pub fn max_total_items(mut self, max_total_items: usize) -> Self {
+        self.max_total_items = max_total_items;
+        self
+    }
+
+    /// Sets the maximum number of bytes that may be copied out of the input.
+    ///
+    /// Strings and byte strings that are borrowed from the input don't count towards this limit.
+    /// Exceeding it results in [`DecodeError::AllocationLimitExceeded`], before the allocation
+    /// happens. There is no limit by default.
+    pub fn max_allocated_bytes(mut self, max_allocated_bytes: usize) -> Self {
+        self.max_allocated_bytes = max_allocated_bytes;
+        self
+    }
+
+    /// Sets whether errors report the path to the value that failed to decode.
+    ///
+    /// The path consists of the map keys and list indices from the top-level value down to the
+    /// failing one, e.g. `entries[12].header.parents[0]`, see [`Location::path`]. Tracking it is
+    /// cheap, but not free: every map key is copied while its value is decoded. It is disabled
+    /// by default.
+    pub fn track_path(mut self, track: bool) -> Self {
+        self.track_path = track;
+        self
+    }
+
+    /// Sets whether strings, byte strings, arrays and maps of indefinite length are accepted.
+    ///
+    /// If disabled, items of indefinite length are rejected with [`DecodeError::IndefiniteSize`].
+    /// It is disabled by default.
+    pub fn allow_indefinite_lengths(mut self, allow: bool) -> Self {
+        self.allow_indefinite_lengths = allow;
+        self
+    }
+
+    /// Sets whether tag 42 is accepted if it isn't encoded as `0xd8 0x2a`, e.g. as
+    /// `0xd9 0x00 0x2a`.
+    ///
+    /// It is disabled by default, where such tags are rejected with [`DecodeError::Mismatch`].
+    pub fn allow_non_minimal_tags(mut self, allow: bool) -> Self {
+        self.allow_non_minimal_tags = allow;
+        self
+    }
+
+    /// Sets whether half-precision floats are accepted.
+    ///
+    /// They are widened to the float type that is decoded, arbitrary values, e.g.
+    /// [`Ipld`](ipld_core::ipld::Ipld), get single-precision floats. It is disabled by default,
+    /// where they are rejected with [`DecodeError::Unsupported`] or [`DecodeError::Mismatch`].
+    pub fn allow_f16_floats(mut self, allow: bool) -> Self {
+        self.allow_f16_floats = allow;
+        self
+    }
+
+    /// Sets whether the accepted deviations from DAG-CBOR are recorded, see
+    /// [`Deserializer::deviations`].
+    ///
+    /// Recording the order of map keys needs a copy of the previous key. It is disabled by
+    /// default.
+    pub fn record_deviations(mut self, record: bool) -> Self {
+        self.record_deviations = record;
+        self
+    }
+
+    /// Sets whether CIDs are fully parsed when they are decoded.
+    ///
+    /// By default, only the multibase prefix of a CID is checked, the rest is left to the type it
+    /// is decoded into. If enabled, the version, the codec and the multihash are checked too, and
+    /// there must not be any bytes after the digest. Violations are rejected with
+    /// [`DecodeError::InvalidCid`], which points to the offending byte.
+    pub fn validate_cids(mut self, validate: bool) -> Self {
+        self.validate_cids = validate;
+        self
+    }
+
+    /// Returns whether any check is enabled that requires ignored values to be fully decoded.
+    fn checks_ignored_values(&self) -> bool {
+        self.require_minimal_encoding
+            || self.require_canonical_map_keys
+            || self.require_f64_floats
+            || self.record_deviations
+            || self.validate_cids
+    }
+}
+
+/// The deviations from DAG-CBOR that were accepted while decoding.
+///
+/// They are only recorded if [`DeserializerOptions::record_deviations`] is enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Deviations {
+    /// The number of strings, arrays and maps of indefinite length.
+    pub indefinite_lengths: usize,
+    /// The number of integers, lengths and tags that weren't encoded with the minimal number of
+    /// bytes.
+    pub non_minimal_encodings: usize,
+    /// The number of half-precision floats.
+    pub f16_floats: usize,
+    /// The number of single-precision floats.
+    pub f32_floats: usize,
+    /// The number of map keys that didn't sort after the previous key.
+    pub unsorted_map_keys: usize,
+    /// The number of map keys that were equal to the previous key.
+    pub duplicate_map_keys: usize,
+}
+
+impl Deviations {
+    /// Returns true if the data was valid DAG-CBOR.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// A Serde `Deserialize`r of DAG-CBOR data.
 #[derive(Debug)]
 pub struct Deserializer<R> {
-    reader: R,
+    reader: PositionReader<R>,
+    options: DeserializerOptions,
+    /// The current nesting depth.
+    depth: usize,
+    /// The number of items declared by the arrays and maps decoded so far.
+    items: usize,
+    /// The number of bytes copied out of the input so far.
+    allocated: usize,
+    /// The offset, initial byte and argument of the most recently decoded head.
+    last_head: (usize, u8, u64),
+    /// The deviations from DAG-CBOR accepted so far.
+    deviations: Deviations,
 }
 
 impl<R> Deserializer<R> {
     /// Constructs a `Deserializer` which reads from a `Read`er.
     pub fn from_reader(reader: R) -> Deserializer<R> {
-        Deserializer { reader }
+        Deserializer {
+            reader: PositionReader {
+                reader,
+                position: 0,
+            },
+            options: DeserializerOptions::default(),
+            depth: 0,
+            items: 0,
+            allocated: 0,
+            last_head: (0, 0, 0),
+            deviations: Deviations::default(),
+        }
+    }
+
+    /// Sets the options that are used for decoding.
+    pub fn with_options(mut self, options: DeserializerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the options that are used for decoding.
+    pub fn options(&self) -> DeserializerOptions {
+        self.options
+    }
+
+    /// Returns the number of bytes that were consumed from the reader so far.
+    pub fn position(&self) -> usize {
+        self.reader.position
+    }
+
+    /// Returns the deviations from DAG-CBOR that were accepted so far.
+    ///
+    /// They are only recorded if [`DeserializerOptions::record_deviations`] is enabled.
+    pub fn deviations(&self) -> Deviations {
+        self.deviations
     }
 }
 
@@ -183,13 +580,68 @@ impl<'de, R: dec::Read<'de>> Deserializer<R> {
 impl<'a> Deserializer<SliceReader<'a>> {
     /// Constructs a `Deserializer` that reads from a slice.
     pub fn from_slice(buf: &'a [u8]) -> Self {
-        Deserializer {
-            reader: SliceReader::new(buf),
-        }
+        Deserializer::from_reader(SliceReader::new(buf))
     }
 }
 
 impl<'de, R: dec::Read<'de>> Deserializer<R> {
+    /// Returns the current location in the input.
+    fn location(&mut self) -> Location {
+        self.location_with(self.position(), &[])
+    }
+
+    /// Returns a location at the given offset.
+    ///
+    /// The already consumed bytes from that offset on are passed in, as they can't be read again.
+    fn location_with(&mut self, offset: usize, consumed: &[u8]) -> Location {
+        let mut window = [0; 16];
+        let mut len = cmp::min(consumed.len(), window.len());
+        window[..len].copy_from_slice(&consumed[..len]);
+        // Errors are reported anyway, hence an error while reading ahead is ignored.
+        if let Ok(buf) = self.reader.fill(window.len() - len) {
+            let buf = buf.as_ref();
+            let buf_len = cmp::min(buf.len(), window.len() - len);
+            window[len..len + buf_len].copy_from_slice(&buf[..buf_len]);
+            len += buf_len;
+        }
+        Location::new(offset, &window[..len])
+    }
+
+    /// Returns the location of an already decoded float.
+    fn float_location(&mut self, offset: usize, marker: u8, value: &[u8]) -> Location {
+        let mut float = [0; 9];
+        float[0] = marker;
+        float[1..=value.len()].copy_from_slice(value);
+        self.location_with(offset, &float[..=value.len()])
+    }
+
+    /// Sets the location of the error to the start of the most recently decoded head.
+    fn at_head(&mut self, mut error: DecodeError<R::Error>) -> DecodeError<R::Error> {
+        let (offset, byte, value) = self.last_head;
+        let arg_len = match dec::low(byte) {
+            0x18 => 1,
+            0x19 => 2,
+            0x1a => 4,
+            0x1b => 8,
+            _ => 0,
+        };
+        let mut head = [0; 9];
+        head[0] = byte;
+        head[1..=arg_len].copy_from_slice(&value.to_be_bytes()[8 - arg_len..]);
+        let location = self.location_with(offset, &head[..=arg_len]);
+        error.location_mut().set_position(location);
+        error
+    }
+
+    /// Sets the location of the error to the current position, unless it is already known.
+    pub(crate) fn locate(&mut self, mut error: DecodeError<R::Error>) -> DecodeError<R::Error> {
+        if error.offset().is_none() {
+            let location = self.location();
+            error.location_mut().set_position(location);
+        }
+        error
+    }
+
     #[allow(clippy::type_complexity)]
     #[inline]
     fn try_step<'a>(
@@ -197,42 +649,587 @@ impl<'de, R: dec::Read<'de>> Deserializer<R> {
         name: &'static str,
     ) -> Result<scopeguard::ScopeGuard<&'a mut Self, fn(&'a mut Self) -> ()>, DecodeError<R::Error>>
     {
-        if self.reader.step_in() {
-            Ok(scopeguard::guard(self, |de| de.reader.step_out()))
+        if self.depth < self.options.max_depth {
+            self.depth += 1;
+            Ok(scopeguard::guard(self, |de| de.depth -= 1))
         } else {
-            Err(DecodeError::DepthOverflow { name })
+            Err(DecodeError::DepthOverflow {
+                name,
+                limit: self.options.max_depth,
+                location: Location::default(),
+            })
+        }
+    }
+
+    /// Skips the next value without decoding it.
+    ///
+    /// Contrary to decoding into [`de::IgnoredAny`], this doesn't check whether the value is valid
+    /// DAG-CBOR. It accepts the same values as [`dec::IgnoredAny`], but obeys the configured
+    /// maximum depth.
+    fn skip(&mut self) -> Result<(), DecodeError<R::Error>> {
+        let name = "ignored-any";
+        let byte = peek_one(name, &mut self.reader)?;
+        match dec::if_major(byte) {
+            major @ major::UNSIGNED | major @ major::NEGATIVE => {
+                self.decode_head(name, major)?;
+            }
+            major @ major::BYTES | major @ major::STRING => match self.decode_len(name, major)? {
+                Some(len) => {
+                    self.check_string_len(name, len)
+                        .map_err(|error| self.at_head(error))?;
+                    self.skip_exact(name, len)?;
+                }
+                // Strings of indefinite length consist of chunks of definite length.
+                None => {
+                    let mut total = 0;
+                    while !self.pull_break(name)? {
+                        let len = self.decode_chunk_len(name, major)?;
+                        total = len.saturating_add(total);
+                        self.check_string_len(name, total)
+                            .map_err(|error| self.at_head(error))?;
+                        self.skip_exact(name, len)?;
+                    }
+                }
+            },
+            major @ major::ARRAY | major @ major::MAP => {
+                let mut de = self.try_step(name)?;
+                // Maps consist of two values per entry.
+                let per_entry = if major == major::MAP { 2 } else { 1 };
+                match de.decode_collection_len(name, major)? {
+                    Some(len) => {
+                        for _ in 0..len {
+                            for _ in 0..per_entry {
+                                de.skip()?;
+                            }
+                        }
+                    }
+                    None => {
+                        while !de.pull_break(name)? {
+                            de.count_items(per_entry)?;
+                            for _ in 0..per_entry {
+                                de.skip()?;
+                            }
+                        }
+                    }
+                }
+            }
+            major::TAG => {
+                let mut de = self.try_step(name)?;
+                de.decode_head(name, major::TAG)?;
+                de.skip()?;
+            }
+            major::SIMPLE => {
+                let len = match byte {
+                    marker::FALSE | marker::TRUE | marker::NULL | marker::UNDEFINED => 0,
+                    marker::F16 => 2,
+                    marker::F32 => 4,
+                    marker::F64 => 8,
+                    _ => {
+                        return Err(DecodeError::Unsupported {
+                            name,
+                            found: byte,
+                            location: Location::default(),
+                        })
+                    }
+                };
+                self.skip_exact(name, len + 1)?;
+            }
+            _ => {
+                return Err(DecodeError::Unsupported {
+                    name,
+                    found: byte,
+                    location: Location::default(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes values up to and including the next link and returns the offset of its tag
+    /// together with the CID.
+    ///
+    /// The `cursor` counts the values that are left to decode, the elements of arrays and maps are
+    /// added to it. Hence there is no recursion and the nesting depth isn't limited. Contrary to
+    /// decoding into a value, strings are skipped without copying them, only the UTF-8 of map keys
+    /// is checked.
+    pub(crate) fn next_link(
+        &mut self,
+        cursor: &mut LinkCursor,
+    ) -> Result<Option<(usize, Cid)>, DecodeError<R::Error>> {
+        let name = "links";
+        while cursor.remaining != 0 {
+            let is_key = cursor.take_key();
+            cursor.remaining -= 1;
+            if is_key {
+                self.skip_key()?;
+                continue;
+            }
+            let byte = peek_one(name, &mut self.reader)?;
+            match dec::if_major(byte) {
+                major @ major::BYTES | major @ major::STRING => {
+                    let len = self
+                        .decode_len(name, major)?
+                        .ok_or_else(|| self.indefinite_size(byte))?;
+                    self.check_string_len(name, len)
+                        .map_err(|error| self.at_head(error))?;
+                    self.skip_exact(name, len)?;
+                }
+                major @ major::ARRAY | major @ major::MAP => {
+                    let len = self
+                        .decode_collection_len(name, major)?
+                        .ok_or_else(|| self.indefinite_size(byte))?;
+                    // Maps consist of two values per entry. The count can only saturate for
+                    // lengths that exceed the input anyway.
+                    let per_entry = if major == major::MAP { 2 } else { 1 };
+                    cursor.remaining = cursor
+                        .remaining
+                        .saturating_add(len.saturating_mul(per_entry));
+                    if major == major::MAP && len != 0 {
+                        cursor.enter_map(len);
+                    }
+                }
+                major::TAG => {
+                    let offset = self.position();
+                    let cid = self.deserialize_cid(CidVisitor)?;
+                    return Ok(Some((offset, cid)));
+                }
+                // Integers, floats and simple values are checked like when they are decoded.
+                _ => {
+                    de::Deserializer::deserialize_any(&mut *self, de::IgnoredAny)?;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks that the next value is a map key of valid UTF-8 and skips it.
+    fn skip_key(&mut self) -> Result<(), DecodeError<R::Error>> {
+        let name = "map key";
+        let byte = peek_one(name, &mut self.reader)?;
+        if byte == (major::STRING << 5) | marker::START {
+            self.reader.advance(1);
+            return Err(self.indefinite_size(byte));
+        }
+        let mut scratch = [0; KEY_SCRATCH_LEN];
+        let key = self.decode_buf_into(name, major::STRING, &mut scratch)?;
+        if core::str::from_utf8(key.as_slice()).is_err() {
+            return Err(self.at_head(DecodeError::RequireUtf8 {
+                name,
+                location: Location::default(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Returns the error for an item of indefinite length, whose initial byte was just consumed.
+    fn indefinite_size(&mut self, byte: u8) -> DecodeError<R::Error> {
+        DecodeError::IndefiniteSize {
+            location: self.location_with(self.position() - 1, &[byte]),
+        }
+    }
+
+    /// Consumes the break marker that ends an item of indefinite length, if it is next.
+    fn pull_break(&mut self, name: &'static str) -> Result<bool, DecodeError<R::Error>> {
+        if peek_one(name, &mut self.reader)? == marker::BREAK {
+            self.reader.advance(1);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Checks the length of a string against the configured maximum.
+    fn check_string_len(
+        &self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        if len > self.options.max_string_len {
+            return Err(DecodeError::StringTooLong {
+                name,
+                len,
+                limit: self.options.max_string_len,
+                location: Location::default(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds to the number of decoded items and checks it against the configured maximum.
+    fn count_items(&mut self, count: usize) -> Result<(), DecodeError<R::Error>> {
+        let items = self.items.saturating_add(count);
+        if items > self.options.max_total_items {
+            return Err(DecodeError::TooManyItems {
+                limit: self.options.max_total_items,
+                location: Location::default(),
+            });
+        }
+        self.items = items;
+        Ok(())
+    }
+
+    /// Adds to the number of allocated bytes and checks it against the configured maximum.
+    fn count_allocation(&mut self, len: usize) -> Result<(), DecodeError<R::Error>> {
+        let allocated = self.allocated.saturating_add(len);
+        if allocated > self.options.max_allocated_bytes {
+            return Err(DecodeError::AllocationLimitExceeded {
+                limit: self.options.max_allocated_bytes,
+                location: Location::default(),
+            });
+        }
+        self.allocated = allocated;
+        Ok(())
+    }
+
+    /// Skips `len` bytes of the input.
+    fn skip_exact(
+        &mut self,
+        name: &'static str,
+        mut len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        while len != 0 {
+            let readlen = cmp::min(self.reader.fill(len)?.as_ref().len(), len);
+            if readlen == 0 {
+                return Err(DecodeError::Eof {
+                    name,
+                    expect: Len::new(len),
+                    location: Location::default(),
+                });
+            }
+            self.reader.advance(readlen);
+            len -= readlen;
+        }
+        Ok(())
+    }
+
+    /// Decodes the argument of a head, checking that it is minimally encoded if required.
+    #[inline]
+    fn decode_head(&mut self, name: &'static str, major: u8) -> Result<u64, DecodeError<R::Error>> {
+        let offset = self.position();
+        let (byte, value) = pull_head(name, major, &mut self.reader)?;
+        self.last_head = (offset, byte, value);
+        if !is_minimal(byte, value) {
+            if self.options.require_minimal_encoding {
+                let error = DecodeError::NonMinimalEncoding {
+                    name,
+                    found: byte,
+                    location: Location::default(),
+                };
+                return Err(self.at_head(error));
+            }
+            if self.options.record_deviations {
+                self.deviations.non_minimal_encodings += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Decodes the length of a byte string, text string, array or map.
+    ///
+    /// `None` is returned for items of indefinite length.
+    #[inline]
+    fn decode_len(
+        &mut self,
+        name: &'static str,
+        major: u8,
+    ) -> Result<Option<usize>, DecodeError<R::Error>> {
+        let byte = peek_one(name, &mut self.reader)?;
+        if byte == (major << 5) | marker::START {
+            self.reader.advance(1);
+            if self.options.record_deviations {
+                self.deviations.indefinite_lengths += 1;
+            }
+            return Ok(None);
+        }
+        let len = self.decode_head(name, major)?;
+        match usize::try_from(len) {
+            Ok(len) => Ok(Some(len)),
+            Err(_) => {
+                let error = DecodeError::CastOverflow {
+                    name,
+                    location: Location::default(),
+                };
+                Err(self.at_head(error))
+            }
+        }
+    }
+
+    /// Decodes the length of a chunk of a string of indefinite length.
+    #[inline]
+    fn decode_chunk_len(
+        &mut self,
+        name: &'static str,
+        major: u8,
+    ) -> Result<usize, DecodeError<R::Error>> {
+        self.decode_len(name, major)?
+            .ok_or(DecodeError::RequireLength {
+                name,
+                found: Len::Indefinite,
+                location: Location::default(),
+            })
+    }
+
+    /// Decodes the length of an array or map and checks it against the configured limits.
+    ///
+    /// `None` is returned for items of indefinite length.
+    #[inline]
+    fn decode_collection_len(
+        &mut self,
+        name: &'static str,
+        major: u8,
+    ) -> Result<Option<usize>, DecodeError<R::Error>> {
+        let len = self.decode_len(name, major)?;
+        if let Some(len) = len {
+            if len > self.options.max_collection_len {
+                let error = DecodeError::CollectionTooLong {
+                    name,
+                    len,
+                    limit: self.options.max_collection_len,
+                    location: Location::default(),
+                };
+                return Err(self.at_head(error));
+            }
+            // Maps consist of two items per entry.
+            let items = if major == major::MAP {
+                len.saturating_mul(2)
+            } else {
+                len
+            };
+            self.count_items(items)
+                .map_err(|error| self.at_head(error))?;
+        }
+        Ok(len)
+    }
+
+    /// Decodes an unsigned or negative integer.
+    #[inline]
+    fn decode_integer(&mut self, name: &'static str) -> Result<i128, DecodeError<R::Error>> {
+        let byte = peek_one(name, &mut self.reader)?;
+        match dec::if_major(byte) {
+            major::UNSIGNED => Ok(self.decode_head(name, major::UNSIGNED)?.into()),
+            // CBOR supports negative integers up to -2^64, hence the i128.
+            major::NEGATIVE => Ok(-1 - i128::from(self.decode_head(name, major::NEGATIVE)?)),
+            _ => Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            }),
+        }
+    }
+
+    /// Decodes a big integer (tag 2 or 3) into its sign and its magnitude.
+    ///
+    /// Big integers are not part of DAG-CBOR, they are only decoded for compatibility.
+    fn decode_bignum(&mut self, name: &'static str) -> Result<(bool, u128), DecodeError<R::Error>> {
+        let negative = match self.decode_head(name, major::TAG)? {
+            2 => false,
+            3 => true,
+            _ => {
+                let error = DecodeError::Mismatch {
+                    name,
+                    found: self.last_head.1,
+                    location: Location::default(),
+                };
+                return Err(self.at_head(error));
+            }
+        };
+        let buf = self.decode_buf(name, major::BYTES)?;
+        if buf.len() > 16 {
+            let error = DecodeError::LengthOverflow {
+                name,
+                found: Len::new(buf.len()),
+                location: Location::default(),
+            };
+            return Err(self.at_head(error));
+        }
+        let mut bytes = [0; 16];
+        bytes[16 - buf.len()..].copy_from_slice(&buf);
+        Ok((negative, u128::from_be_bytes(bytes)))
+    }
+
+    /// Decodes a single-precision float.
+    ///
+    /// Single-precision floats are not valid in strict DAG-CBOR, they are only decoded for
+    /// compatibility.
+    fn decode_f32(&mut self, name: &'static str) -> Result<f32, DecodeError<R::Error>> {
+        let offset = self.position();
+        let value = <f32>::decode(&mut self.reader)?;
+        // DAG-CBOR forbids NaN and Infinity.
+        if !value.is_finite() {
+            let location = self.float_location(offset, marker::F32, &value.to_be_bytes());
+            return Err(DecodeError::Mismatch {
+                name,
+                found: marker::F32,
+                location,
+            });
+        }
+        if self.options.record_deviations {
+            self.deviations.f32_floats += 1;
+        }
+        Ok(value)
+    }
+
+    /// Decodes a half-precision float.
+    ///
+    /// Half-precision floats are not part of DAG-CBOR, they are only decoded for compatibility.
+    fn decode_f16(&mut self, name: &'static str) -> Result<f32, DecodeError<R::Error>> {
+        let offset = self.position();
+        let mut buf = [0; 3];
+        pull_exact(name, &mut self.reader, &mut buf)?;
+        let value = f16_to_f32(u16::from_be_bytes([buf[1], buf[2]]));
+        // DAG-CBOR forbids NaN and Infinity.
+        if !value.is_finite() {
+            let location = self.float_location(offset, buf[0], &buf[1..]);
+            return Err(DecodeError::Mismatch {
+                name,
+                found: buf[0],
+                location,
+            });
+        }
+        if self.options.record_deviations {
+            self.deviations.f16_floats += 1;
+        }
+        Ok(value)
+    }
+
+    /// Decodes a byte or text string. The data is borrowed if the reader supports it.
+    fn decode_buf(
+        &mut self,
+        name: &'static str,
+        major: u8,
+    ) -> Result<Cow<'de, [u8]>, DecodeError<R::Error>> {
+        match self.decode_buf_into(name, major, &mut [])? {
+            Buf::Borrowed(buf) => Ok(Cow::Borrowed(buf)),
+            // Only empty strings fit into no scratch space, hence this doesn't allocate.
+            Buf::Scratch(buf) => Ok(Cow::Owned(buf.to_vec())),
+            Buf::Owned(buf) => Ok(Cow::Owned(buf)),
+        }
+    }
+
+    /// Decodes a byte or text string. The data is borrowed if the reader supports it, else it is
+    /// copied into `scratch` if it fits, so that nothing is allocated.
+    fn decode_buf_into<'s>(
+        &mut self,
+        name: &'static str,
+        major: u8,
+        scratch: &'s mut [u8],
+    ) -> Result<Buf<'de, 's>, DecodeError<R::Error>> {
+        match self.decode_len(name, major)? {
+            Some(len) => {
+                self.check_string_len(name, len)
+                    .map_err(|error| self.at_head(error))?;
+                if let dec::Reference::Long(buf) = self.reader.fill(len)? {
+                    if buf.len() >= len {
+                        self.reader.advance(len);
+                        return Ok(Buf::Borrowed(&buf[..len]));
+                    }
+                }
+                if let Some(scratch) = scratch.get_mut(..len) {
+                    pull_exact(name, &mut self.reader, scratch)?;
+                    return Ok(Buf::Scratch(scratch));
+                }
+                self.count_allocation(len)
+                    .map_err(|error| self.at_head(error))?;
+                let mut buf = Vec::new();
+                self.pull_into(name, len, &mut buf)?;
+                Ok(Buf::Owned(buf))
+            }
+            // Strings of indefinite length consist of chunks of definite length.
+            None => {
+                if !self.options.allow_indefinite_lengths {
+                    return Err(self.indefinite_size((major << 5) | marker::START));
+                }
+                let mut buf = Vec::new();
+                while !self.pull_break(name)? {
+                    let len = self.decode_chunk_len(name, major)?;
+                    self.check_string_len(name, buf.len().saturating_add(len))
+                        .map_err(|error| self.at_head(error))?;
+                    self.count_allocation(len)
+                        .map_err(|error| self.at_head(error))?;
+                    self.pull_into(name, len, &mut buf)?;
+                }
+                Ok(Buf::Owned(buf))
+            }
         }
     }
 
+    /// Appends `len` bytes from the reader to the given buffer.
+    fn pull_into(
+        &mut self,
+        name: &'static str,
+        mut len: usize,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), DecodeError<R::Error>> {
+        // Don't trust the length for pre-allocating, the input might be truncated.
+        buf.reserve(cmp::min(len, 16 * 1024));
+        while len != 0 {
+            let readbuf = self.reader.fill(len)?;
+            let readbuf = readbuf.as_ref();
+            if readbuf.is_empty() {
+                return Err(DecodeError::Eof {
+                    name,
+                    expect: Len::new(len),
+                    location: Location::default(),
+                });
+            }
+            let readlen = cmp::min(readbuf.len(), len);
+            buf.extend_from_slice(&readbuf[..readlen]);
+            self.reader.advance(readlen);
+            len -= readlen;
+        }
+        Ok(())
+    }
+
     #[inline]
     fn deserialize_cid<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
     where
         V: Visitor<'de>,
     {
         // DAG-CBOR only supports tag 42 (CID), encoded minimally as `0xd8 0x2a`.
-        let head = pull_one("tag head", &mut self.reader)?;
+        let offset = self.position();
+        let head = peek_one("tag head", &mut self.reader)?;
+        // Tags with longer heads are decoded, so that non-minimal ones can be rejected as such.
+        let check_tag =
+            self.options.allow_non_minimal_tags || self.options.require_minimal_encoding;
+        if check_tag && matches!(head, 0xd9..=0xdb) {
+            if self.decode_head("CBOR tag", major::TAG)? != u64::from(CBOR_TAGS_CID) {
+                let error = DecodeError::Mismatch {
+                    name: "CBOR tag head",
+                    found: head,
+                    location: Location::default(),
+                };
+                return Err(self.at_head(error));
+            }
+            return visitor.visit_newtype_struct(&mut CidDeserializer(self, offset));
+        }
         if head != 0xd8 {
             return Err(DecodeError::Mismatch {
                 name: "CBOR tag head",
                 found: head,
+                location: Location::default(),
             });
         }
+        self.reader.advance(1);
 
-        let tag = pull_one("tag", &mut self.reader)?;
+        let tag = peek_one("tag", &mut self.reader)?;
         if tag != CBOR_TAGS_CID {
             return Err(DecodeError::Mismatch {
                 name: "CBOR tag",
                 found: tag,
+                location: self.location_with(offset, &[head]),
             });
         }
-        visitor.visit_newtype_struct(&mut CidDeserializer(self))
+        self.reader.advance(1);
+        visitor.visit_newtype_struct(&mut CidDeserializer(self, offset))
     }
 
     /// This method should be called after a value has been deserialized to ensure there is no
     /// trailing data in the input source.
     pub fn end(&mut self) -> Result<(), DecodeError<R::Error>> {
         match peek_one("end", &mut self.reader) {
-            Ok(_) => Err(DecodeError::TrailingData),
+            Ok(_) => Err(DecodeError::TrailingData {
+                location: self.location(),
+            }),
             Err(DecodeError::Eof { .. }) => Ok(()),
             Err(error) => Err(error),
         }
@@ -248,16 +1245,9 @@ impl<'de, R: dec::Read<'de>> Deserializer<R> {
     {
         let mut de = self.try_step(name)?;
         let mut seq = Accessor::array(&mut de)?;
-        let value = seq.len;
         let res = visitor.visit_seq(&mut seq)?;
-        match seq.len {
-            0 => Ok(res),
-            remaining => Err(DecodeError::LengthMismatch {
-                name,
-                expect: value - remaining,
-                value,
-            }),
-        }
+        seq.end(name, 1)?;
+        Ok(res)
     }
 
     fn visit_map<V>(
@@ -270,66 +1260,44 @@ impl<'de, R: dec::Read<'de>> Deserializer<R> {
     {
         let mut de = self.try_step(name)?;
         let mut map = Accessor::map(&mut de)?;
-        let value = map.len;
         let res = visitor.visit_map(&mut map)?;
-        match map.len {
-            0 => Ok(res),
-            remaining => Err(DecodeError::LengthMismatch {
-                name,
-                expect: value - remaining,
-                value,
-            }),
-        }
+        map.end(name, 2)?;
+        Ok(res)
     }
-}
-
-macro_rules! deserialize_type {
-    ( @ $t:ty , $name:ident , $visit:ident ) => {
-        #[inline]
-        fn $name<V>(self, visitor: V) -> Result<V::Value, Self::Error>
-        where V: Visitor<'de>
-        {
-            let value = <$t>::decode(&mut self.reader)?;
-            visitor.$visit(value)
-        }
-    };
-    ( $( $t:ty , $name:ident , $visit:ident );* $( ; )? ) => {
-        $(
-            deserialize_type!(@ $t, $name, $visit);
-        )*
-    };
-}
-
-impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
-    type Error = DecodeError<R::Error>;
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    fn visit_any<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
     where
         V: Visitor<'de>,
     {
         let name = "any";
-        let mut de = self.try_step(name)?;
-        let de = &mut *de;
+        let de = self;
 
         let byte = peek_one(name, &mut de.reader)?;
-        if is_indefinite(byte) {
-            return Err(DecodeError::IndefiniteSize);
+        let major = dec::if_major(byte);
+        let allowed = matches!(
+            major,
+            major::BYTES | major::STRING | major::ARRAY | major::MAP
+        ) && de.options.allow_indefinite_lengths;
+        if is_indefinite(byte) && !allowed {
+            return Err(DecodeError::IndefiniteSize {
+                location: Location::default(),
+            });
         }
-        match dec::if_major(byte) {
-            major::UNSIGNED => de.deserialize_u64(visitor),
+        match major {
+            major::UNSIGNED => serde::Deserializer::deserialize_u64(de, visitor),
             major::NEGATIVE => {
                 // CBOR supports negative integers up to -2^64 which is less than i64::MIN. Only
                 // treat it as i128, if it is outside the i64 range.
-                let value = i128::decode(&mut de.reader)?;
+                let value = de.decode_integer(name)?;
                 match i64::try_from(value) {
                     Ok(value_i64) => visitor.visit_i64(value_i64),
                     Err(_) => visitor.visit_i128(value),
                 }
             }
-            major::BYTES => de.deserialize_byte_buf(visitor),
-            major::STRING => de.deserialize_string(visitor),
-            major::ARRAY => de.deserialize_seq(visitor),
-            major::MAP => de.deserialize_map(visitor),
+            major::BYTES => de.visit_bytes(visitor),
+            major::STRING => de.visit_str(visitor),
+            major::ARRAY => de.visit_seq("array", visitor),
+            major::MAP => de.visit_map("map", visitor),
             // The only supported tag is tag 42 (CID).
             major::TAG => de.deserialize_cid(visitor),
             major::SIMPLE => match byte {
@@ -345,83 +1313,380 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
                     de.reader.advance(1);
                     visitor.visit_none()
                 }
-                marker::F32 => de.deserialize_f32(visitor),
-                marker::F64 => de.deserialize_f64(visitor),
-                _ => Err(DecodeError::Unsupported { name, found: byte }),
+                marker::F16 if de.options.allow_f16_floats => {
+                    let value = de.decode_f16(name)?;
+                    visitor.visit_f32(value)
+                }
+                marker::F32 => de.visit_f32(visitor),
+                marker::F64 => de.visit_f64(visitor),
+                _ => Err(DecodeError::Unsupported {
+                    name,
+                    found: byte,
+                    location: Location::default(),
+                }),
             },
-            _ => Err(DecodeError::Unsupported { name, found: byte }),
+            _ => Err(DecodeError::Unsupported {
+                name,
+                found: byte,
+                location: Location::default(),
+            }),
+        }
+    }
+
+    fn visit_bool<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let value = bool::decode(&mut self.reader)?;
+        visitor.visit_bool(value)
+    }
+
+    fn visit_i128<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "i128";
+        let byte = peek_one(name, &mut self.reader)?;
+        let value = if dec::if_major(byte) == major::TAG {
+            let (negative, value) = self.decode_bignum(name)?;
+            let value = i128::try_from(value).map_err(|_| {
+                self.at_head(DecodeError::CastOverflow {
+                    name,
+                    location: Location::default(),
+                })
+            })?;
+            if negative {
+                -1 - value
+            } else {
+                value
+            }
+        } else {
+            self.decode_integer(name)?
+        };
+        visitor.visit_i128(value)
+    }
+
+    fn visit_u128<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "u128";
+        let byte = peek_one(name, &mut self.reader)?;
+        let value = if dec::if_major(byte) == major::TAG {
+            match self.decode_bignum(name)? {
+                (false, value) => value,
+                (true, _) => {
+                    return Err(DecodeError::Mismatch {
+                        name,
+                        found: byte,
+                        location: Location::default(),
+                    })
+                }
+            }
+        } else {
+            let value = self.decode_integer(name)?;
+            u128::try_from(value).map_err(|_| {
+                self.at_head(DecodeError::CastOverflow {
+                    name,
+                    location: Location::default(),
+                })
+            })?
+        };
+        visitor.visit_u128(value)
+    }
+
+    fn visit_f32<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "f32";
+        // DAG-CBOR strictly requires all floats to be encoded as f64.
+        // When deserializing to f32, we need to handle f64 encoding.
+        // Unless f64 floats are required, we also accept f32 encoding,
+        // although a strict DAG-CBOR implementation should reject it
+        // (please don't write new data with f32 encoding).
+        let byte = peek_one(name, &mut self.reader)?;
+        match byte {
+            marker::F32 if self.options.require_f64_floats => Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            }),
+            marker::F32 => {
+                let value = self.decode_f32(name)?;
+                visitor.visit_f32(value)
+            }
+            marker::F16 if self.options.allow_f16_floats => {
+                let value = self.decode_f16(name)?;
+                visitor.visit_f32(value)
+            }
+            marker::F64 => {
+                // DAG-CBOR always uses f64 encoding, even for f32 values
+                let offset = self.position();
+                let value = <f64>::decode(&mut self.reader)?;
+                let location = self.float_location(offset, byte, &value.to_be_bytes());
+
+                // DAG-CBOR forbids NaN and Infinity.
+                if !value.is_finite() {
+                    return Err(DecodeError::Mismatch {
+                        name,
+                        found: byte,
+                        location,
+                    });
+                }
+
+                let f32_value = value as f32;
+
+                // Check if conversion causes overflow to infinity
+                if !f32_value.is_finite() {
+                    // The f64 value is finite but becomes infinite when converted to f32
+                    return Err(DecodeError::Msg(
+                        "Float value out of range for f32".into(),
+                        location,
+                    ));
+                }
+
+                // Check if the f64 value is exactly representable as f32. Reject values that
+                // lose precision in the conversion. If you must decode to f32, then you should
+                // only encode f32 values.
+                if (f32_value as f64).to_bits() != value.to_bits() {
+                    return Err(DecodeError::Msg(
+                        "Float value has more precision than f32 can represent, loss of precision"
+                            .into(),
+                        location,
+                    ));
+                }
+
+                visitor.visit_f32(f32_value)
+            }
+            _ => Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            }),
+        }
+    }
+
+    fn visit_f64<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "f64";
+        let offset = self.position();
+        let byte = peek_one(name, &mut self.reader)?;
+        // Smaller floats are widened, if they are accepted.
+        match byte {
+            marker::F32 if !self.options.require_f64_floats => {
+                let value = self.decode_f32(name)?;
+                return visitor.visit_f64(f64::from(value));
+            }
+            marker::F16 if self.options.allow_f16_floats => {
+                let value = self.decode_f16(name)?;
+                return visitor.visit_f64(f64::from(value));
+            }
+            _ => {}
+        }
+        let value = <f64>::decode(&mut self.reader)?;
+        // DAG-CBOR forbids NaN and Infinity.
+        if !value.is_finite() {
+            let location = self.float_location(offset, byte, &value.to_be_bytes());
+            return Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location,
+            });
+        }
+        visitor.visit_f64(value)
+    }
+
+    fn visit_bytes<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        match self.decode_buf("bytes", major::BYTES)? {
+            Cow::Borrowed(buf) => visitor.visit_borrowed_bytes(buf),
+            Cow::Owned(buf) => visitor.visit_byte_buf(buf),
+        }
+    }
+
+    fn visit_str<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "str";
+        match self.decode_buf(name, major::STRING)? {
+            Cow::Borrowed(buf) => {
+                let buf = core::str::from_utf8(buf).map_err(|_| {
+                    self.at_head(DecodeError::RequireUtf8 {
+                        name,
+                        location: Location::default(),
+                    })
+                })?;
+                visitor.visit_borrowed_str(buf)
+            }
+            Cow::Owned(buf) => {
+                let buf = String::from_utf8(buf).map_err(|_| {
+                    self.at_head(DecodeError::RequireUtf8 {
+                        name,
+                        location: Location::default(),
+                    })
+                })?;
+                visitor.visit_string(buf)
+            }
+        }
+    }
+
+    fn visit_option<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "option";
+        let byte = peek_one(name, &mut self.reader)?;
+        if byte != marker::NULL {
+            let mut de = self.try_step(name)?;
+            visitor.visit_some(&mut **de)
+        } else {
+            self.reader.advance(1);
+            visitor.visit_none()
+        }
+    }
+
+    fn visit_unit<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let name = "unit";
+        let byte = peek_one(name, &mut self.reader)?;
+        if byte == marker::NULL {
+            self.reader.advance(1);
+            visitor.visit_unit()
+        } else {
+            Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            })
+        }
+    }
+
+    fn visit_newtype_struct<V>(
+        &mut self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        if name == CID_SERDE_PRIVATE_IDENTIFIER {
+            self.deserialize_cid(visitor)
+        } else {
+            visitor.visit_newtype_struct(self)
+        }
+    }
+
+    fn visit_enum<V>(
+        &mut self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        let mut de = self.try_step(name)?;
+        let accessor = EnumAccessor::enum_(&mut de)?;
+        visitor.visit_enum(accessor)
+    }
+
+    fn visit_ignored_any<V>(&mut self, visitor: V) -> Result<V::Value, DecodeError<R::Error>>
+    where
+        V: Visitor<'de>,
+    {
+        if self.options.checks_ignored_values() {
+            // Ignored values need to be checked as well, hence decode them fully.
+            self.visit_any(de::IgnoredAny)?;
+        } else {
+            self.skip()?;
+        }
+        visitor.visit_unit()
+    }
+}
+
+macro_rules! deserialize_integer {
+    ( @ $t:ty , $name:ident , $visit:ident ) => {
+        #[inline]
+        fn $name<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where V: Visitor<'de>
+        {
+            let name = stringify!($t);
+            let value = match self.decode_integer(name) {
+                Ok(value) => value,
+                Err(error) => return Err(self.locate(error)),
+            };
+            let value = <$t>::try_from(value).map_err(|_| {
+                self.at_head(DecodeError::CastOverflow { name, location: Location::default() })
+            })?;
+            visitor.$visit(value).map_err(|error| self.locate(error))
         }
+    };
+    ( $( $t:ty , $name:ident , $visit:ident );* $( ; )? ) => {
+        $(
+            deserialize_integer!(@ $t, $name, $visit);
+        )*
+    };
+}
+
+impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
+    type Error = DecodeError<R::Error>;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_any(visitor).map_err(|error| self.locate(error))
     }
 
-    deserialize_type!(
-        bool,       deserialize_bool,       visit_bool;
+    #[inline]
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_bool(visitor).map_err(|error| self.locate(error))
+    }
 
+    deserialize_integer!(
         i8,         deserialize_i8,         visit_i8;
         i16,        deserialize_i16,        visit_i16;
         i32,        deserialize_i32,        visit_i32;
         i64,        deserialize_i64,        visit_i64;
-        i128,       deserialize_i128,       visit_i128;
 
         u8,         deserialize_u8,         visit_u8;
         u16,        deserialize_u16,        visit_u16;
         u32,        deserialize_u32,        visit_u32;
         u64,        deserialize_u64,        visit_u64;
-        u128,       deserialize_u128,       visit_u128;
     );
 
     #[inline]
-    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        let name = "f32";
-        // DAG-CBOR strictly requires all floats to be encoded as f64.
-        // When deserializing to f32, we need to handle f64 encoding.
-        // We also accept f32 encoding, although a strict DAG-CBOR
-        // implementation should reject it (please don't write new data
-        // with f32 encoding).
-        let byte = peek_one(name, &mut self.reader)?;
-        match byte {
-            marker::F32 => {
-                // Note: f32 encoding is not valid in strict DAG-CBOR.
-                let value = <f32>::decode(&mut self.reader)?;
-                // DAG-CBOR forbids NaN and Infinity.
-                if !value.is_finite() {
-                    return Err(DecodeError::Mismatch { name, found: byte });
-                }
-                visitor.visit_f32(value)
-            }
-            marker::F64 => {
-                // DAG-CBOR always uses f64 encoding, even for f32 values
-                let value = <f64>::decode(&mut self.reader)?;
-
-                // DAG-CBOR forbids NaN and Infinity.
-                if !value.is_finite() {
-                    return Err(DecodeError::Mismatch { name, found: byte });
-                }
-
-                let f32_value = value as f32;
-
-                // Check if conversion causes overflow to infinity
-                if !f32_value.is_finite() {
-                    // The f64 value is finite but becomes infinite when converted to f32
-                    return Err(DecodeError::Msg("Float value out of range for f32".into()));
-                }
+        self.visit_i128(visitor).map_err(|error| self.locate(error))
+    }
 
-                // Check if the f64 value is exactly representable as f32. Reject values that
-                // lose precision in the conversion. If you must decode to f32, then you should
-                // only encode f32 values.
-                if (f32_value as f64).to_bits() != value.to_bits() {
-                    return Err(DecodeError::Msg(
-                        "Float value has more precision than f32 can represent, loss of precision"
-                            .into(),
-                    ));
-                }
+    #[inline]
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_u128(visitor).map_err(|error| self.locate(error))
+    }
 
-                visitor.visit_f32(f32_value)
-            }
-            _ => Err(DecodeError::Mismatch { name, found: byte }),
-        }
+    #[inline]
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_f32(visitor).map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -429,14 +1694,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        let name = "f64";
-        let byte = peek_one(name, &mut self.reader)?;
-        let value = <f64>::decode(&mut self.reader)?;
-        // DAG-CBOR forbids NaN and Infinity.
-        if !value.is_finite() {
-            return Err(DecodeError::Mismatch { name, found: byte });
-        }
-        visitor.visit_f64(value)
+        self.visit_f64(visitor).map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -455,10 +1713,8 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        match <types::Bytes<Cow<[u8]>>>::decode(&mut self.reader)?.0 {
-            Cow::Borrowed(buf) => visitor.visit_borrowed_bytes(buf),
-            Cow::Owned(buf) => visitor.visit_byte_buf(buf),
-        }
+        self.visit_bytes(visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -474,10 +1730,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        match <Cow<str>>::decode(&mut self.reader)? {
-            Cow::Borrowed(buf) => visitor.visit_borrowed_str(buf),
-            Cow::Owned(buf) => visitor.visit_string(buf),
-        }
+        self.visit_str(visitor).map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -493,15 +1746,8 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        let name = "option";
-        let byte = peek_one(name, &mut self.reader)?;
-        if byte != marker::NULL {
-            let mut de = self.try_step(name)?;
-            visitor.visit_some(&mut **de)
-        } else {
-            self.reader.advance(1);
-            visitor.visit_none()
-        }
+        self.visit_option(visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -509,13 +1755,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        let name = "unit";
-        let byte = pull_one(name, &mut self.reader)?;
-        if byte == marker::NULL {
-            visitor.visit_unit()
-        } else {
-            Err(DecodeError::Mismatch { name, found: byte })
-        }
+        self.visit_unit(visitor).map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -539,11 +1779,8 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        if name == CID_SERDE_PRIVATE_IDENTIFIER {
-            self.deserialize_cid(visitor)
-        } else {
-            visitor.visit_newtype_struct(self)
-        }
+        self.visit_newtype_struct(name, visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -552,6 +1789,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
         V: Visitor<'de>,
     {
         self.visit_seq("array", visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -560,6 +1798,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
         V: Visitor<'de>,
     {
         self.visit_seq("tuple", visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -573,6 +1812,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
         V: Visitor<'de>,
     {
         self.visit_seq(name, visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -581,6 +1821,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
         V: Visitor<'de>,
     {
         self.visit_map("map", visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -594,6 +1835,7 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
         V: Visitor<'de>,
     {
         self.visit_map(name, visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -606,9 +1848,8 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        let mut de = self.try_step(name)?;
-        let accessor = EnumAccessor::enum_(&mut de)?;
-        visitor.visit_enum(accessor)
+        self.visit_enum(name, visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -624,8 +1865,8 @@ impl<'de, R: dec::Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: Visitor<'de>,
     {
-        let _ignore = dec::IgnoredAny::decode(&mut self.reader)?;
-        visitor.visit_unit()
+        self.visit_ignored_any(visitor)
+            .map_err(|error| self.locate(error))
     }
 
     #[inline]
@@ -664,6 +1905,9 @@ where
             return None;
         }
 
+        // The resource limits apply to each value separately.
+        self.de.items = 0;
+        self.de.allocated = 0;
         let result = serde::Deserialize::deserialize(&mut self.de);
 
         match result {
@@ -675,26 +1919,174 @@ where
 
 struct Accessor<'a, R> {
     de: &'a mut Deserializer<R>,
-    len: usize,
+    /// The number of remaining entries, `None` if the length is indefinite and the end wasn't
+    /// reached yet.
+    len: Option<usize>,
+    /// The previous map key, only tracked if canonical map keys are required.
+    prev_key: Option<Vec<u8>>,
+    /// The number of entries decoded so far, which is the index of the next array element.
+    index: usize,
+    /// The key of the current map entry, only tracked if the path is tracked.
+    key: Option<String>,
 }
 
 impl<'de, 'a, R: dec::Read<'de>> Accessor<'a, R> {
     #[inline]
     fn array(de: &'a mut Deserializer<R>) -> Result<Accessor<'a, R>, DecodeError<R::Error>> {
-        let len = types::Array::len(&mut de.reader)?;
-        len.map_or_else(
-            || Err(DecodeError::IndefiniteSize),
-            move |len| Ok(Accessor { de, len }),
-        )
+        let len = de.decode_collection_len("array", major::ARRAY)?;
+        Accessor::new(de, len)
     }
 
     #[inline]
     fn map(de: &'a mut Deserializer<R>) -> Result<Accessor<'a, R>, DecodeError<R::Error>> {
-        let len = types::Map::len(&mut de.reader)?;
-        len.map_or_else(
-            || Err(DecodeError::IndefiniteSize),
-            move |len| Ok(Accessor { de, len }),
-        )
+        let len = de.decode_collection_len("map", major::MAP)?;
+        Accessor::new(de, len)
+    }
+
+    #[inline]
+    fn new(
+        de: &'a mut Deserializer<R>,
+        len: Option<usize>,
+    ) -> Result<Accessor<'a, R>, DecodeError<R::Error>> {
+        if len.is_none() && !de.options.allow_indefinite_lengths {
+            return Err(DecodeError::IndefiniteSize {
+                location: Location::default(),
+            });
+        }
+        Ok(Accessor {
+            de,
+            len,
+            prev_key: None,
+            index: 0,
+            key: None,
+        })
+    }
+
+    /// Advances to the next entry, returns false if there are no more entries.
+    fn next_entry(
+        &mut self,
+        name: &'static str,
+        items: usize,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        match &mut self.len {
+            Some(0) => return Ok(false),
+            Some(len) => *len -= 1,
+            None => {
+                if self.de.pull_break(name)? {
+                    self.len = Some(0);
+                    return Ok(false);
+                }
+                // The items of collections of indefinite length are counted as they come.
+                self.de.count_items(items)?;
+            }
+        }
+        self.index += 1;
+        Ok(true)
+    }
+
+    /// Checks that all entries were consumed.
+    fn end(self, name: &'static str, items: usize) -> Result<(), DecodeError<R::Error>> {
+        let remaining = match self.len {
+            Some(0) => return Ok(()),
+            Some(remaining) => remaining,
+            None => {
+                let mut remaining = 0;
+                while !self.de.pull_break(name)? {
+                    for _ in 0..items {
+                        self.de.skip()?;
+                    }
+                    remaining += 1;
+                }
+                if remaining == 0 {
+                    return Ok(());
+                }
+                remaining
+            }
+        };
+        Err(DecodeError::LengthMismatch {
+            name,
+            expect: self.index,
+            value: self.index + remaining,
+            location: Location::default(),
+        })
+    }
+
+    /// Decodes a map key, in order to check it or to record it for the path.
+    fn checked_key<K>(&mut self, seed: K) -> Result<K::Value, DecodeError<R::Error>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let name = "map key";
+        let key = self.de.decode_buf(name, major::STRING)?;
+        if self.de.options.require_canonical_map_keys || self.de.options.record_deviations {
+            self.check_key_order(&key)?;
+        }
+        match key {
+            Cow::Borrowed(buf) => {
+                let key = core::str::from_utf8(buf).map_err(|_| {
+                    self.de.at_head(DecodeError::RequireUtf8 {
+                        name,
+                        location: Location::default(),
+                    })
+                })?;
+                if self.de.options.track_path {
+                    self.key = Some(key.into());
+                }
+                seed.deserialize(BorrowedStrDeserializer::new(key))
+            }
+            Cow::Owned(buf) => {
+                let key = String::from_utf8(buf).map_err(|_| {
+                    self.de.at_head(DecodeError::RequireUtf8 {
+                        name,
+                        location: Location::default(),
+                    })
+                })?;
+                if self.de.options.track_path {
+                    self.key = Some(key.clone());
+                }
+                seed.deserialize(StringDeserializer::new(key))
+            }
+        }
+    }
+
+    /// Checks that a map key sorts after the previous one.
+    ///
+    /// Violations are errors if canonical map keys are required, otherwise they are recorded.
+    fn check_key_order(&mut self, key: &[u8]) -> Result<(), DecodeError<R::Error>> {
+        // Keys are sorted by length first and bytewise second.
+        let order = match &self.prev_key {
+            Some(prev_key) => prev_key
+                .len()
+                .cmp(&key.len())
+                .then_with(|| prev_key[..].cmp(key)),
+            None => cmp::Ordering::Less,
+        };
+        let require = self.de.options.require_canonical_map_keys;
+        match order {
+            cmp::Ordering::Less => {}
+            cmp::Ordering::Equal if require => {
+                let error = DecodeError::DuplicateMapKey {
+                    location: Location::default(),
+                };
+                return Err(self.de.at_head(error));
+            }
+            cmp::Ordering::Greater if require => {
+                let error = DecodeError::UnsortedMapKeys {
+                    location: Location::default(),
+                };
+                return Err(self.de.at_head(error));
+            }
+            cmp::Ordering::Equal => self.de.deviations.duplicate_map_keys += 1,
+            cmp::Ordering::Greater => self.de.deviations.unsorted_map_keys += 1,
+        }
+        match &mut self.prev_key {
+            Some(prev_key) => {
+                prev_key.clear();
+                prev_key.extend_from_slice(key);
+            }
+            None => self.prev_key = Some(key.to_vec()),
+        }
+        Ok(())
     }
 }
 
@@ -709,17 +2101,25 @@ where
     where
         T: de::DeserializeSeed<'de>,
     {
-        if self.len > 0 {
-            self.len -= 1;
-            Ok(Some(seed.deserialize(&mut *self.de)?))
-        } else {
-            Ok(None)
+        if !self.next_entry("array", 1)? {
+            return Ok(None);
+        }
+        match seed.deserialize(&mut *self.de) {
+            Ok(value) => Ok(Some(value)),
+            Err(mut error) => {
+                if self.de.options.track_path {
+                    error
+                        .location_mut()
+                        .prepend_path(PathSegment::Index(self.index - 1));
+                }
+                Err(error)
+            }
         }
     }
 
     #[inline]
     fn size_hint(&self) -> Option<usize> {
-        Some(self.len)
+        self.len
     }
 }
 
@@ -731,18 +2131,24 @@ impl<'de, R: dec::Read<'de>> de::MapAccess<'de> for Accessor<'_, R> {
     where
         K: de::DeserializeSeed<'de>,
     {
-        if self.len > 0 {
-            let name = "map key";
-            // Map keys must be strings in DAG-CBOR.
-            let byte = peek_one(name, &mut self.de.reader)?;
-            if dec::if_major(byte) != major::STRING {
-                return Err(DecodeError::Mismatch { name, found: byte });
-            }
-            self.len -= 1;
-            Ok(Some(seed.deserialize(&mut *self.de)?))
-        } else {
-            Ok(None)
+        if !self.next_entry("map", 2)? {
+            return Ok(None);
+        }
+        let name = "map key";
+        // Map keys must be strings in DAG-CBOR.
+        let byte = peek_one(name, &mut self.de.reader)?;
+        if dec::if_major(byte) != major::STRING {
+            return Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            });
+        }
+        let options = &self.de.options;
+        if options.require_canonical_map_keys || options.track_path || options.record_deviations {
+            return Ok(Some(self.checked_key(seed)?));
         }
+        Ok(Some(seed.deserialize(&mut *self.de)?))
     }
 
     #[inline]
@@ -750,12 +2156,18 @@ impl<'de, R: dec::Read<'de>> de::MapAccess<'de> for Accessor<'_, R> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        seed.deserialize(&mut *self.de)
+        let key = self.key.take();
+        seed.deserialize(&mut *self.de).map_err(|mut error| {
+            if let Some(key) = key {
+                error.location_mut().prepend_path(PathSegment::Key(key));
+            }
+            error
+        })
     }
 
     #[inline]
     fn size_hint(&self) -> Option<usize> {
-        Some(self.len)
+        self.len
     }
 }
 
@@ -778,7 +2190,11 @@ impl<'de, 'a, R: dec::Read<'de>> EnumAccessor<'a, R> {
                 de.reader.advance(1);
                 Ok(EnumAccessor { de })
             }
-            _ => Err(DecodeError::Mismatch { name, found: byte }),
+            _ => Err(DecodeError::Mismatch {
+                name,
+                found: byte,
+                location: Location::default(),
+            }),
         }
     }
 }
@@ -844,6 +2260,83 @@ where
     }
 }
 
+/// The size of the scratch space for the bytes of a CID, which fits CIDs with digests of up to 64
+/// bytes, so that CIDs can be decoded from readers without allocating.
+const CID_SCRATCH_LEN: usize = 128;
+
+/// The size of the scratch space for map keys, so that the keys of most maps can be checked
+/// without allocating when scanning readers for links.
+const KEY_SCRATCH_LEN: usize = 128;
+
+/// The state of a scan for links, see [`Deserializer::next_link`].
+#[derive(Debug)]
+pub(crate) struct LinkCursor {
+    /// The number of values that are left to scan.
+    remaining: usize,
+    /// The innermost map that has keys left, as the value of `remaining` at its next key and the
+    /// number of keys left. It's kept apart, so that only nested maps need an allocation.
+    map: Option<(usize, usize)>,
+    /// The maps around the innermost one that have keys left.
+    outer: Vec<(usize, usize)>,
+}
+
+impl LinkCursor {
+    /// Starts a scan of a single value.
+    pub(crate) fn new() -> Self {
+        Self {
+            remaining: 1,
+            map: None,
+            outer: Vec::new(),
+        }
+    }
+
+    /// Records a map with the given number of entries, whose keys and values were just added.
+    fn enter_map(&mut self, len: usize) {
+        if let Some(map) = self.map.replace((self.remaining, len)) {
+            self.outer.push(map);
+        }
+    }
+
+    /// Returns whether the next value is a map key and moves on to the key after it.
+    ///
+    /// All values within a map value are scanned before its next key, so the next key is reached
+    /// when `remaining` drops to the number of values that were left after the map value.
+    fn take_key(&mut self) -> bool {
+        match &mut self.map {
+            Some((next_key, keys)) if *next_key == self.remaining => {
+                *keys -= 1;
+                if *keys == 0 {
+                    self.map = self.outer.pop();
+                } else {
+                    *next_key -= 2;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A decoded byte or text string.
+enum Buf<'de, 's> {
+    /// Borrowed from the input.
+    Borrowed(&'de [u8]),
+    /// Copied into scratch space.
+    Scratch(&'s [u8]),
+    /// Copied into an allocation.
+    Owned(Vec<u8>),
+}
+
+impl Buf<'_, '_> {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Buf::Borrowed(buf) => buf,
+            Buf::Scratch(buf) => buf,
+            Buf::Owned(buf) => buf,
+        }
+    }
+}
+
 /// Deserialize a DAG-CBOR encoded CID.
 ///
 /// This is without the CBOR tag information. It is only the CBOR byte string identifier (major
@@ -852,7 +2345,9 @@ where
 /// The reason for not including the CBOR tag information is the [`Value`] implementation. That one
 /// starts to parse the bytes, before we could interfere. If the data only includes a CID, we are
 /// parsing over the tag to determine whether it is a CID or not and go from there.
-struct CidDeserializer<'a, R>(&'a mut Deserializer<R>);
+///
+/// The second field is the offset of the tag within the input.
+struct CidDeserializer<'a, R>(&'a mut Deserializer<R>, usize);
 
 impl<'de, 'a, R: dec::Read<'de>> de::Deserializer<'de> for &'a mut CidDeserializer<'a, R> {
     type Error = DecodeError<R::Error>;
@@ -873,32 +2368,48 @@ impl<'de, 'a, R: dec::Read<'de>> de::Deserializer<'de> for &'a mut CidDeserializ
         let byte = peek_one("cid", &mut self.0.reader)?;
         match dec::if_major(byte) {
             major::BYTES => {
-                // CBOR encoded CIDs have a zero byte prefix we have to remove.
-                match <types::Bytes<Cow<[u8]>>>::decode(&mut self.0.reader)?.0 {
-                    Cow::Borrowed(buf) => {
-                        if buf.len() <= 1 || buf[0] != 0 {
-                            Err(DecodeError::Msg("Invalid CID".into()))
-                        } else {
-                            visitor.visit_borrowed_bytes(&buf[1..])
-                        }
+                let mut scratch = [0; CID_SCRATCH_LEN];
+                let buf = self.0.decode_buf_into("cid", major::BYTES, &mut scratch)?;
+                let bytes = buf.as_slice();
+                if self.0.options.validate_cids {
+                    if let Err((offset, reason)) = check_cid(bytes) {
+                        let start = self.0.position() - bytes.len();
+                        return Err(DecodeError::InvalidCid {
+                            reason,
+                            location: Location::new(start + offset, &bytes[offset..]),
+                        });
                     }
-                    Cow::Owned(mut buf) => {
-                        if buf.len() <= 1 || buf[0] != 0 {
-                            Err(DecodeError::Msg("Invalid CID".into()))
-                        } else {
-                            buf.remove(0);
-                            visitor.visit_byte_buf(buf)
-                        }
+                }
+                // CBOR encoded CIDs have a zero byte prefix we have to remove.
+                if bytes.len() <= 1 || bytes[0] != 0 {
+                    return Err(DecodeError::Msg("Invalid CID".into(), Location::default()));
+                }
+                match buf {
+                    Buf::Borrowed(buf) => visitor.visit_borrowed_bytes(&buf[1..]),
+                    Buf::Scratch(buf) => visitor.visit_bytes(&buf[1..]),
+                    Buf::Owned(mut buf) => {
+                        buf.remove(0);
+                        visitor.visit_byte_buf(buf)
                     }
                 }
             }
             _ => Err(DecodeError::Unsupported {
                 name: "cid",
                 found: byte,
+                location: Location::default(),
             }),
         }
     }
 
+    #[inline]
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(de::IgnoredAny)?;
+        visitor.visit_unit()
+    }
+
     fn deserialize_newtype_struct<V: de::Visitor<'de>>(
         self,
         name: &str,
@@ -906,6 +2417,12 @@ impl<'de, 'a, R: dec::Read<'de>> de::Deserializer<'de> for &'a mut CidDeserializ
     ) -> Result<V::Value, Self::Error> {
         if name == CID_SERDE_PRIVATE_IDENTIFIER {
             self.deserialize_bytes(visitor)
+        } else if name == LINK_OFFSET_IDENTIFIER {
+            visitor.visit_seq(LinkOffsetAccess {
+                de: &mut *self.0,
+                offset: self.1,
+                index: 0,
+            })
         } else {
             Err(de::Error::custom([
                 "This deserializer must not be called on newtype structs other than one named `",
@@ -916,11 +2433,175 @@ impl<'de, 'a, R: dec::Read<'de>> de::Deserializer<'de> for &'a mut CidDeserializ
     }
 
     serde::forward_to_deserialize_any! {
-        bool byte_buf char enum f32 f64 i8 i16 i32 i64 identifier ignored_any map option seq str
+        bool byte_buf char enum f32 f64 i8 i16 i32 i64 identifier map option seq str
         string struct tuple tuple_struct u8 u16 u32 u64 unit unit_struct
     }
 }
 
+/// Visits a CID, which is passed on as newtype struct.
+struct CidVisitor;
+
+impl<'de> Visitor<'de> for CidVisitor {
+    type Value = Cid;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a CID")
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Cid, D::Error> {
+        deserializer.deserialize_bytes(BytesToCidVisitor)
+    }
+}
+
+/// The name of a newtype struct, which makes the deserializer of a CID visit a sequence of the
+/// offset of the tag in the input, the CID and the offset right after it.
+pub(crate) const LINK_OFFSET_IDENTIFIER: &str = "$__private_serde_ipld_dagcbor_link_offset";
+
+/// Visits the offset of a CID, the CID and the offset after it, see [`LINK_OFFSET_IDENTIFIER`].
+struct LinkOffsetAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    offset: usize,
+    /// The number of elements visited so far.
+    index: u8,
+}
+
+impl<'de, R: dec::Read<'de>> de::SeqAccess<'de> for LinkOffsetAccess<'_, R> {
+    type Error = DecodeError<R::Error>;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        self.index += 1;
+        match self.index {
+            1 => seed.deserialize(self.offset.into_deserializer()).map(Some),
+            2 => seed
+                .deserialize(&mut CidDeserializer(self.de, self.offset))
+                .map(Some),
+            3 => seed
+                .deserialize(self.de.position().into_deserializer())
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Checks that the content of a CID byte string, including the multibase prefix, is a binary CID.
+///
+/// On failure, the offset of the offending byte within `bytes` is returned.
+pub(crate) fn check_cid(bytes: &[u8]) -> Result<(), (usize, InvalidCidReason)> {
+    if bytes.first() != Some(&0) {
+        return Err((0, InvalidCidReason::MissingMultibasePrefix));
+    }
+    // A CIDv0 is a bare SHA2-256 multihash.
+    if bytes.get(1) == Some(&0x12) {
+        return if bytes.len() == 35 && bytes[2] == 0x20 {
+            Ok(())
+        } else {
+            Err((1, InvalidCidReason::InvalidCidV0))
+        };
+    }
+    let mut offset = 1;
+    let version = read_varint(bytes, &mut offset)?;
+    if version != 1 {
+        return Err((1, InvalidCidReason::UnsupportedVersion(version)));
+    }
+    // The codec and the multihash code may be any value.
+    read_varint(bytes, &mut offset)?;
+    read_varint(bytes, &mut offset)?;
+    let digest_len = read_varint(bytes, &mut offset)?;
+    let remaining = (bytes.len() - offset) as u64;
+    if remaining < digest_len {
+        Err((bytes.len(), InvalidCidReason::Truncated))
+    } else if remaining > digest_len {
+        Err((
+            offset + digest_len as usize,
+            InvalidCidReason::TrailingBytes,
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads an unsigned varint as used by multiformats, which has at most 9 bytes and must be
+/// minimally encoded.
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Result<u64, (usize, InvalidCidReason)> {
+    let start = *offset;
+    let mut value = 0;
+    for index in 0..9 {
+        let byte = *bytes
+            .get(start + index)
+            .ok_or((start + index, InvalidCidReason::Truncated))?;
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            if byte == 0 && index > 0 {
+                break;
+            }
+            *offset = start + index + 1;
+            return Ok(value);
+        }
+    }
+    Err((start, InvalidCidReason::InvalidVarint))
+}
+
+/// A reader that keeps track of the number of consumed bytes.
+#[derive(Debug)]
+struct PositionReader<R> {
+    reader: R,
+    position: usize,
+}
+
+impl<'de, R: dec::Read<'de>> dec::Read<'de> for PositionReader<R> {
+    type Error = R::Error;
+
+    #[inline]
+    fn fill<'short>(
+        &'short mut self,
+        want: usize,
+    ) -> Result<dec::Reference<'de, 'short>, Self::Error> {
+        self.reader.fill(want)
+    }
+
+    #[inline]
+    fn advance(&mut self, n: usize) {
+        self.position += n;
+        self.reader.advance(n);
+    }
+
+    #[inline]
+    fn step_in(&mut self) -> bool {
+        self.reader.step_in()
+    }
+
+    #[inline]
+    fn step_out(&mut self) {
+        self.reader.step_out()
+    }
+}
+
+/// Converts the bits of a half-precision float into a single-precision float, which is lossless.
+fn f16_to_f32(bits: u16) -> f32 {
+    let negative = bits & 0x8000 != 0;
+    let exponent = u32::from((bits >> 10) & 0x1f);
+    let mantissa = u32::from(bits & 0x3ff);
+    let value = match exponent {
+        // Subnormal numbers are a multiple of 2^-24.
+        0 => mantissa as f32 * f32::from_bits(0x3380_0000),
+        0x1f if mantissa == 0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        // Rebias the exponent from 15 to 127.
+        _ => f32::from_bits(((exponent + 112) << 23) | (mantissa << 13)),
+    };
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
 /// Check if byte is a major type with indefinite length.
 #[inline]
 pub fn is_indefinite(byte: u8) -> bool {
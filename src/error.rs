@@ -1,8 +1,10 @@
 //! When serializing or deserializing DAG-CBOR goes wrong.
 
 use alloc::{
+    boxed::Box,
     collections::TryReserveError,
     string::{String, ToString},
+    vec::Vec,
 };
 use core::{convert::Infallible, fmt};
 
@@ -10,14 +12,64 @@ pub use cbor4ii::core::error::{ArithmeticOverflow, Len};
 use serde::{de, ser};
 
 /// An encoding error.
+///
+/// Except for [`EncodeError::Write`], the errors contain the [`Path`] to the value that failed to
+/// encode, e.g. `metrics.samples[314].value`.
 #[derive(Debug)]
 pub enum EncodeError<E> {
     /// Custom error message.
-    Msg(String),
+    Msg(String, Path),
     /// IO Error.
     Write(E),
+    /// The data is nested deeper than the configured maximum, see
+    /// [`SerializerOptions::max_depth`](crate::ser::SerializerOptions::max_depth).
+    DepthOverflow {
+        /// The configured maximum depth.
+        limit: usize,
+        /// The path to the value that is nested too deep.
+        path: Path,
+    },
+    /// The output would exceed the configured maximum size, see
+    /// [`SerializerOptions::max_output_size`](crate::ser::SerializerOptions::max_output_size).
+    OutputTooLarge {
+        /// The configured maximum size in bytes.
+        limit: usize,
+        /// The path to the value whose output exceeded the limit.
+        path: Path,
+    },
+    /// A map contains the same key more than once, which isn't valid DAG-CBOR.
+    DuplicateMapKey {
+        /// The duplicated key.
+        key: String,
+        /// The path to the map.
+        path: Path,
+    },
+    /// The buffer for the entries of a map or the elements of a list of unknown length couldn't
+    /// grow.
+    AllocationFailed {
+        /// The error of the allocation.
+        error: TryReserveError,
+        /// The path to the value whose output didn't fit into the buffer.
+        path: Path,
+    },
+}
+
+/// The error of [`to_slice`](crate::ser::to_slice) when the slice is too small for the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferFull;
+
+impl fmt::Display for BufferFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the buffer is too small for the output")
+    }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for BufferFull {}
+
+#[cfg(not(feature = "std"))]
+impl ser::StdError for BufferFull {}
+
 impl<E> From<E> for EncodeError<E> {
     fn from(err: E) -> EncodeError<E> {
         EncodeError::Write(err)
@@ -27,14 +79,14 @@ impl<E> From<E> for EncodeError<E> {
 #[cfg(feature = "std")]
 impl<E: std::error::Error + 'static> ser::Error for EncodeError<E> {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        EncodeError::Msg(msg.to_string())
+        EncodeError::Msg(msg.to_string(), Path::default())
     }
 }
 
 #[cfg(not(feature = "std"))]
 impl<E: fmt::Debug> ser::Error for EncodeError<E> {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        EncodeError::Msg(msg.to_string())
+        EncodeError::Msg(msg.to_string(), Path::default())
     }
 }
 
@@ -42,8 +94,9 @@ impl<E: fmt::Debug> ser::Error for EncodeError<E> {
 impl<E: std::error::Error + 'static> std::error::Error for EncodeError<E> {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            EncodeError::Msg(_) => None,
             EncodeError::Write(err) => Some(err),
+            EncodeError::AllocationFailed { error, .. } => Some(error),
+            _ => None,
         }
     }
 }
@@ -51,9 +104,105 @@ impl<E: std::error::Error + 'static> std::error::Error for EncodeError<E> {
 #[cfg(not(feature = "std"))]
 impl<E: fmt::Debug> ser::StdError for EncodeError<E> {}
 
+impl<E> EncodeError<E> {
+    /// Returns the kind of the error, for matching on it without caring about the details.
+    pub fn kind(&self) -> EncodeErrorKind {
+        match self {
+            EncodeError::Msg(..) => EncodeErrorKind::Msg,
+            EncodeError::Write(_) => EncodeErrorKind::Write,
+            EncodeError::DepthOverflow { .. } => EncodeErrorKind::DepthOverflow,
+            EncodeError::OutputTooLarge { .. } => EncodeErrorKind::OutputTooLarge,
+            EncodeError::DuplicateMapKey { .. } => EncodeErrorKind::DuplicateMapKey,
+            EncodeError::AllocationFailed { .. } => EncodeErrorKind::AllocationFailed,
+        }
+    }
+
+    /// Returns the path to the value that failed to encode.
+    ///
+    /// It is empty if the error occurred at the top-level value, or if it's an
+    /// [`EncodeError::Write`] error.
+    pub fn path(&self) -> &Path {
+        match self {
+            EncodeError::Msg(_, path)
+            | EncodeError::DepthOverflow { path, .. }
+            | EncodeError::OutputTooLarge { path, .. }
+            | EncodeError::DuplicateMapKey { path, .. }
+            | EncodeError::AllocationFailed { path, .. } => path,
+            EncodeError::Write(_) => &EMPTY_PATH,
+        }
+    }
+
+    /// Adds a segment to the start of the path.
+    pub(crate) fn prepend_path(&mut self, segment: PathSegment) {
+        match self {
+            EncodeError::Msg(_, path)
+            | EncodeError::DepthOverflow { path, .. }
+            | EncodeError::OutputTooLarge { path, .. }
+            | EncodeError::DuplicateMapKey { path, .. }
+            | EncodeError::AllocationFailed { path, .. } => path.0.insert(0, segment),
+            EncodeError::Write(_) => {}
+        }
+    }
+}
+
 impl<E: fmt::Debug> fmt::Display for EncodeError<E> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            EncodeError::Msg(msg, _) => f.write_str(msg)?,
+            EncodeError::Write(err) => write!(f, "failed to write the output: {:?}", err)?,
+            EncodeError::DepthOverflow { limit, .. } => {
+                write!(f, "nesting depth exceeds the limit of {}", limit)?
+            }
+            EncodeError::OutputTooLarge { limit, .. } => {
+                write!(f, "output exceeds the limit of {} bytes", limit)?
+            }
+            EncodeError::DuplicateMapKey { key, .. } => write!(f, "duplicate map key {:?}", key)?,
+            EncodeError::AllocationFailed { error, .. } => {
+                write!(f, "failed to buffer the output: {}", error)?
+            }
+        }
+        if !self.path().is_empty() {
+            write!(f, " in {}", self.path())?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of an [`EncodeError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EncodeErrorKind {
+    /// See [`EncodeError::Msg`].
+    Msg,
+    /// See [`EncodeError::Write`].
+    Write,
+    /// See [`EncodeError::DepthOverflow`].
+    DepthOverflow,
+    /// See [`EncodeError::OutputTooLarge`].
+    OutputTooLarge,
+    /// See [`EncodeError::DuplicateMapKey`].
+    DuplicateMapKey,
+    /// See [`EncodeError::AllocationFailed`].
+    AllocationFailed,
+}
+
+impl EncodeErrorKind {
+    /// Returns a stable identifier of the kind, e.g. for error responses of an API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EncodeErrorKind::Msg => "msg",
+            EncodeErrorKind::Write => "write",
+            EncodeErrorKind::DepthOverflow => "depth_overflow",
+            EncodeErrorKind::OutputTooLarge => "output_too_large",
+            EncodeErrorKind::DuplicateMapKey => "duplicate_map_key",
+            EncodeErrorKind::AllocationFailed => "allocation_failed",
+        }
+    }
+}
+
+impl fmt::Display for EncodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
@@ -63,24 +212,200 @@ impl<E: fmt::Debug> From<cbor4ii::core::error::EncodeError<E>> for EncodeError<E
             cbor4ii::core::error::EncodeError::Write(e) => EncodeError::Write(e),
             // Future-proof against new upstream variants without an SDK bump; loses structured info
             // but preserves the Display string.
-            _ => EncodeError::Msg(err.to_string()),
+            _ => EncodeError::Msg(err.to_string(), Path::default()),
         }
     }
 }
 
+/// The maximum number of bytes a [`Location`] keeps of the input.
+const WINDOW_LEN: usize = 16;
+
+/// Where in the input a decoding error occurred.
+///
+/// The details are boxed, so that they don't bloat every `Result` on the decoding path.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Location(Option<Box<LocationDetails>>);
+
+#[derive(Clone, Default, PartialEq, Eq)]
+struct LocationDetails {
+    offset: Option<usize>,
+    window: [u8; WINDOW_LEN],
+    window_len: usize,
+    path: Path,
+}
+
+/// The path of an error without any segments.
+static EMPTY_PATH: Path = Path(Vec::new());
+
+impl Location {
+    /// Creates a location at the given offset, with the input bytes that start at that offset.
+    pub(crate) fn new(offset: usize, window: &[u8]) -> Self {
+        let window_len = window.len().min(WINDOW_LEN);
+        let mut details = LocationDetails {
+            offset: Some(offset),
+            window: [0; WINDOW_LEN],
+            window_len,
+            path: Path::default(),
+        };
+        details.window[..window_len].copy_from_slice(&window[..window_len]);
+        Location(Some(Box::new(details)))
+    }
+
+    /// Returns the byte offset in the input, if it is known.
+    pub fn offset(&self) -> Option<usize> {
+        self.0.as_ref().and_then(|details| details.offset)
+    }
+
+    /// Returns a few bytes of the input, starting at the offset.
+    ///
+    /// It may be shorter than expected or empty, if the input ended or the bytes were no longer
+    /// available when the error occurred.
+    pub fn window(&self) -> &[u8] {
+        match &self.0 {
+            Some(details) => &details.window[..details.window_len],
+            None => &[],
+        }
+    }
+
+    /// Returns the path to the value that failed to decode.
+    ///
+    /// It is empty if the error occurred at the top-level value, or if path tracking isn't
+    /// enabled, see [`DeserializerOptions::track_path`](crate::de::DeserializerOptions::track_path).
+    pub fn path(&self) -> &Path {
+        match &self.0 {
+            Some(details) => &details.path,
+            None => &EMPTY_PATH,
+        }
+    }
+
+    /// Replaces the offset and window with the ones of the given location, the path is kept.
+    pub(crate) fn set_position(&mut self, position: Location) {
+        let path = self
+            .0
+            .take()
+            .map(|details| details.path)
+            .unwrap_or_default();
+        *self = position;
+        if !path.0.is_empty() {
+            self.0.get_or_insert_with(Box::default).path = path;
+        }
+    }
+
+    /// Adds a segment to the start of the path.
+    pub(crate) fn prepend_path(&mut self, segment: PathSegment) {
+        self.0
+            .get_or_insert_with(Box::default)
+            .path
+            .0
+            .insert(0, segment);
+    }
+}
+
+impl fmt::Debug for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Location");
+        debug
+            .field("offset", &self.offset())
+            .field("window", &format_args!("{}", HexWindow(self.window())));
+        if !self.path().is_empty() {
+            debug.field("path", &format_args!("{}", self.path()));
+        }
+        debug.finish()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset() {
+            Some(offset) if self.window().is_empty() => write!(f, "at byte {}", offset)?,
+            Some(offset) => write!(f, "at byte {} ({})", offset, HexWindow(self.window()))?,
+            None => f.write_str("at unknown position")?,
+        }
+        if !self.path().is_empty() {
+            write!(f, " in {}", self.path())?;
+        }
+        Ok(())
+    }
+}
+
+/// The path from the top-level value to a nested value, e.g. `entries[12].header.parents[0]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Path(Vec<PathSegment>);
+
+impl Path {
+    /// Returns the segments, starting at the top-level value.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    /// Returns true if the path points to the top-level value.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds a segment to the end of the path.
+    pub(crate) fn push(&mut self, segment: PathSegment) {
+        self.0.push(segment);
+    }
+
+    /// Removes the last segment of the path.
+    pub(crate) fn pop(&mut self) {
+        self.0.pop();
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) if index == 0 => f.write_str(key)?,
+                PathSegment::Key(key) => write!(f, ".{}", key)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single step of a [`Path`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathSegment {
+    /// The value of a map entry with this key.
+    Key(String),
+    /// The element of a list at this index.
+    Index(usize),
+}
+
+/// Formats bytes as space separated hex.
+struct HexWindow<'a>(&'a [u8]);
+
+impl fmt::Display for HexWindow<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if index != 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
 /// A decoding error.
 #[derive(Debug)]
 pub enum DecodeError<E> {
     /// Custom error message.
-    Msg(String),
+    Msg(String, Location),
     /// IO error.
-    Read(E),
+    Read(E, Location),
     /// End of file.
     Eof {
         /// Type name.
         name: &'static str,
         /// Expected length.
         expect: Len,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Unexpected byte.
     Mismatch {
@@ -88,6 +413,8 @@ pub enum DecodeError<E> {
         name: &'static str,
         /// Unexpected byte.
         found: u8,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Unsupported byte.
     Unsupported {
@@ -95,6 +422,8 @@ pub enum DecodeError<E> {
         name: &'static str,
         /// Unsupported byte.
         found: u8,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Length wasn't large enough.
     RequireLength {
@@ -102,16 +431,22 @@ pub enum DecodeError<E> {
         name: &'static str,
         /// Available length.
         found: Len,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Required a borrow.
     RequireBorrowed {
         /// Type name.
         name: &'static str,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Invalid UTF-8.
     RequireUtf8 {
         /// Type name.
         name: &'static str,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Length overflow.
     LengthOverflow {
@@ -119,11 +454,15 @@ pub enum DecodeError<E> {
         name: &'static str,
         /// Encoded length.
         found: Len,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Cast overflow.
     CastOverflow {
         /// Type name.
         name: &'static str,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Arithmetic overflow.
     ArithmeticOverflow {
@@ -131,11 +470,17 @@ pub enum DecodeError<E> {
         name: &'static str,
         /// Direction of the overflow.
         ty: ArithmeticOverflow,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Recursion limit reached.
     DepthOverflow {
         /// Type name.
         name: &'static str,
+        /// The configured maximum depth.
+        limit: usize,
+        /// Where the error occurred.
+        location: Location,
     },
     /// CBOR array/map length didn't match what serde expected.
     LengthMismatch {
@@ -145,30 +490,251 @@ pub enum DecodeError<E> {
         expect: usize,
         /// Actual length.
         value: usize,
+        /// Where the error occurred.
+        location: Location,
     },
     /// Trailing data.
-    TrailingData,
+    TrailingData {
+        /// Where the error occurred.
+        location: Location,
+    },
     /// Indefinite sized item was encountered.
-    IndefiniteSize,
+    IndefiniteSize {
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// An integer, tag or length wasn't encoded with the minimal number of bytes.
+    NonMinimalEncoding {
+        /// Type name.
+        name: &'static str,
+        /// The initial byte of the non-minimal head.
+        found: u8,
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// Map keys weren't sorted in canonical order.
+    UnsortedMapKeys {
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// A map contained the same key more than once.
+    DuplicateMapKey {
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// A string or byte string was longer than the configured maximum.
+    StringTooLong {
+        /// Type name.
+        name: &'static str,
+        /// Encoded length.
+        len: usize,
+        /// The configured maximum length.
+        limit: usize,
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// An array or map had more elements than the configured maximum.
+    CollectionTooLong {
+        /// Type name.
+        name: &'static str,
+        /// Encoded length.
+        len: usize,
+        /// The configured maximum length.
+        limit: usize,
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// The data contained more items than the configured maximum.
+    TooManyItems {
+        /// The configured maximum number of items.
+        limit: usize,
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// Decoding needed to allocate more bytes than the configured maximum.
+    AllocationLimitExceeded {
+        /// The configured maximum number of bytes.
+        limit: usize,
+        /// Where the error occurred.
+        location: Location,
+    },
+    /// The bytes of a CID aren't a valid binary CID, see
+    /// [`DeserializerOptions::validate_cids`](crate::de::DeserializerOptions::validate_cids).
+    InvalidCid {
+        /// What is wrong with the CID.
+        reason: InvalidCidReason,
+        /// Where the error occurred, which is the offending byte of the CID.
+        location: Location,
+    },
+}
+
+/// Why a CID is invalid, see [`DecodeError::InvalidCid`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidCidReason {
+    /// The bytes don't start with the `0x00` multibase prefix.
+    MissingMultibasePrefix,
+    /// The bytes ended before the CID was complete.
+    Truncated,
+    /// A varint is longer than 9 bytes or not minimally encoded.
+    InvalidVarint,
+    /// The CID version isn't 1, and it isn't a CIDv0 either.
+    UnsupportedVersion(u64),
+    /// The CID looks like a CIDv0, but isn't a SHA2-256 multihash with a 32 byte digest.
+    InvalidCidV0,
+    /// There are bytes after the multihash digest.
+    TrailingBytes,
+}
+
+impl fmt::Display for InvalidCidReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidCidReason::MissingMultibasePrefix => {
+                f.write_str("the multibase prefix 00 is missing")
+            }
+            InvalidCidReason::Truncated => f.write_str("it is truncated"),
+            InvalidCidReason::InvalidVarint => f.write_str("it contains an invalid varint"),
+            InvalidCidReason::UnsupportedVersion(version) => {
+                write!(f, "version {} is not supported", version)
+            }
+            InvalidCidReason::InvalidCidV0 => {
+                f.write_str("a CIDv0 must be a SHA2-256 multihash with a 32 byte digest")
+            }
+            InvalidCidReason::TrailingBytes => f.write_str("bytes follow the multihash digest"),
+        }
+    }
+}
+
+impl<E> DecodeError<E> {
+    /// Returns the byte offset in the input at which the error occurred, if it is known.
+    pub fn offset(&self) -> Option<usize> {
+        self.location().offset()
+    }
+
+    /// Returns where the error occurred.
+    pub fn location(&self) -> &Location {
+        match self {
+            DecodeError::Msg(_, location)
+            | DecodeError::Read(_, location)
+            | DecodeError::Eof { location, .. }
+            | DecodeError::Mismatch { location, .. }
+            | DecodeError::Unsupported { location, .. }
+            | DecodeError::RequireLength { location, .. }
+            | DecodeError::RequireBorrowed { location, .. }
+            | DecodeError::RequireUtf8 { location, .. }
+            | DecodeError::LengthOverflow { location, .. }
+            | DecodeError::CastOverflow { location, .. }
+            | DecodeError::ArithmeticOverflow { location, .. }
+            | DecodeError::DepthOverflow { location, .. }
+            | DecodeError::LengthMismatch { location, .. }
+            | DecodeError::TrailingData { location }
+            | DecodeError::IndefiniteSize { location }
+            | DecodeError::NonMinimalEncoding { location, .. }
+            | DecodeError::UnsortedMapKeys { location }
+            | DecodeError::DuplicateMapKey { location }
+            | DecodeError::StringTooLong { location, .. }
+            | DecodeError::CollectionTooLong { location, .. }
+            | DecodeError::TooManyItems { location, .. }
+            | DecodeError::AllocationLimitExceeded { location, .. }
+            | DecodeError::InvalidCid { location, .. } => location,
+        }
+    }
+
+    /// Returns the kind of the error, for matching on it without caring about the details.
+    pub fn kind(&self) -> DecodeErrorKind {
+        match self {
+            DecodeError::Msg(..) => DecodeErrorKind::Msg,
+            DecodeError::Read(..) => DecodeErrorKind::Read,
+            DecodeError::Eof { .. } => DecodeErrorKind::Eof,
+            DecodeError::Mismatch { .. } => DecodeErrorKind::Mismatch,
+            DecodeError::Unsupported { .. } => DecodeErrorKind::Unsupported,
+            DecodeError::RequireLength { .. } => DecodeErrorKind::RequireLength,
+            DecodeError::RequireBorrowed { .. } => DecodeErrorKind::RequireBorrowed,
+            DecodeError::RequireUtf8 { .. } => DecodeErrorKind::RequireUtf8,
+            DecodeError::LengthOverflow { .. } => DecodeErrorKind::LengthOverflow,
+            DecodeError::CastOverflow { .. } => DecodeErrorKind::CastOverflow,
+            DecodeError::ArithmeticOverflow { .. } => DecodeErrorKind::ArithmeticOverflow,
+            DecodeError::DepthOverflow { .. } => DecodeErrorKind::DepthOverflow,
+            DecodeError::LengthMismatch { .. } => DecodeErrorKind::LengthMismatch,
+            DecodeError::TrailingData { .. } => DecodeErrorKind::TrailingData,
+            DecodeError::IndefiniteSize { .. } => DecodeErrorKind::IndefiniteSize,
+            DecodeError::NonMinimalEncoding { .. } => DecodeErrorKind::NonMinimalEncoding,
+            DecodeError::UnsortedMapKeys { .. } => DecodeErrorKind::UnsortedMapKeys,
+            DecodeError::DuplicateMapKey { .. } => DecodeErrorKind::DuplicateMapKey,
+            DecodeError::StringTooLong { .. } => DecodeErrorKind::StringTooLong,
+            DecodeError::CollectionTooLong { .. } => DecodeErrorKind::CollectionTooLong,
+            DecodeError::TooManyItems { .. } => DecodeErrorKind::TooManyItems,
+            DecodeError::AllocationLimitExceeded { .. } => DecodeErrorKind::AllocationLimitExceeded,
+            DecodeError::InvalidCid { .. } => DecodeErrorKind::InvalidCid,
+        }
+    }
+
+    /// Returns a hint about the DAG-CBOR rule that was violated, if there is one.
+    fn hint(&self) -> Option<&'static str> {
+        match self {
+            DecodeError::Mismatch { name, .. } if name.starts_with("CBOR tag") => {
+                Some("DAG-CBOR only allows tag 42, encoded as d8 2a")
+            }
+            DecodeError::Mismatch {
+                name: "map key", ..
+            } => Some("DAG-CBOR only allows string map keys"),
+            DecodeError::Mismatch { found, .. } if is_float(*found) => {
+                Some("DAG-CBOR only allows finite 64-bit floats")
+            }
+            DecodeError::IndefiniteSize { .. } => Some("DAG-CBOR only allows definite lengths"),
+            DecodeError::UnsortedMapKeys { .. } => {
+                Some("DAG-CBOR sorts map keys by length first and bytewise second")
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn location_mut(&mut self) -> &mut Location {
+        match self {
+            DecodeError::Msg(_, location)
+            | DecodeError::Read(_, location)
+            | DecodeError::Eof { location, .. }
+            | DecodeError::Mismatch { location, .. }
+            | DecodeError::Unsupported { location, .. }
+            | DecodeError::RequireLength { location, .. }
+            | DecodeError::RequireBorrowed { location, .. }
+            | DecodeError::RequireUtf8 { location, .. }
+            | DecodeError::LengthOverflow { location, .. }
+            | DecodeError::CastOverflow { location, .. }
+            | DecodeError::ArithmeticOverflow { location, .. }
+            | DecodeError::DepthOverflow { location, .. }
+            | DecodeError::LengthMismatch { location, .. }
+            | DecodeError::TrailingData { location }
+            | DecodeError::IndefiniteSize { location }
+            | DecodeError::NonMinimalEncoding { location, .. }
+            | DecodeError::UnsortedMapKeys { location }
+            | DecodeError::DuplicateMapKey { location }
+            | DecodeError::StringTooLong { location, .. }
+            | DecodeError::CollectionTooLong { location, .. }
+            | DecodeError::TooManyItems { location, .. }
+            | DecodeError::AllocationLimitExceeded { location, .. }
+            | DecodeError::InvalidCid { location, .. } => location,
+        }
+    }
 }
 
 impl<E> From<E> for DecodeError<E> {
     fn from(err: E) -> DecodeError<E> {
-        DecodeError::Read(err)
+        DecodeError::Read(err, Location::default())
     }
 }
 
 #[cfg(feature = "std")]
 impl<E: std::error::Error + 'static> de::Error for DecodeError<E> {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        DecodeError::Msg(msg.to_string())
+        DecodeError::Msg(msg.to_string(), Location::default())
     }
 }
 
 #[cfg(not(feature = "std"))]
 impl<E: fmt::Debug> de::Error for DecodeError<E> {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        DecodeError::Msg(msg.to_string())
+        DecodeError::Msg(msg.to_string(), Location::default())
     }
 }
 
@@ -176,7 +742,7 @@ impl<E: fmt::Debug> de::Error for DecodeError<E> {
 impl<E: std::error::Error + 'static> std::error::Error for DecodeError<E> {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            DecodeError::Read(err) => Some(err),
+            DecodeError::Read(err, _) => Some(err),
             _ => None,
         }
     }
@@ -187,34 +753,283 @@ impl<E: fmt::Debug> ser::StdError for DecodeError<E> {}
 
 impl<E: fmt::Debug> fmt::Display for DecodeError<E> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            DecodeError::Msg(msg, _) => f.write_str(msg)?,
+            DecodeError::Read(err, _) => write!(f, "failed to read the input: {:?}", err)?,
+            DecodeError::Eof { name, expect, .. } => match expect {
+                Len::Small(len) => write!(
+                    f,
+                    "unexpected end of input while decoding {}, {} more {} expected",
+                    name,
+                    len,
+                    if *len == 1 { "byte was" } else { "bytes were" }
+                )?,
+                _ => write!(f, "unexpected end of input while decoding {}", name)?,
+            },
+            DecodeError::Mismatch {
+                name: "CBOR tag",
+                found,
+                ..
+            } => write!(f, "unsupported CBOR tag {}", found)?,
+            DecodeError::Mismatch {
+                name: "CBOR tag head",
+                found,
+                ..
+            } => write!(f, "unsupported CBOR tag encoding {:02x}", found)?,
+            DecodeError::Mismatch {
+                name: "map key",
+                found,
+                ..
+            } => write!(f, "unexpected {} as map key", describe(*found))?,
+            DecodeError::Mismatch { name, found, .. } => write!(
+                f,
+                "unexpected {} ({:02x}) while decoding {}",
+                describe(*found),
+                found,
+                name
+            )?,
+            DecodeError::Unsupported { name, found, .. } => write!(
+                f,
+                "unsupported {} ({:02x}) while decoding {}",
+                describe(*found),
+                found,
+                name
+            )?,
+            DecodeError::RequireLength { name, .. } => {
+                write!(f, "invalid length while decoding {}", name)?
+            }
+            DecodeError::RequireBorrowed { name, .. } => {
+                write!(f, "{} can't be borrowed from the input", name)?
+            }
+            DecodeError::RequireUtf8 { name, .. } => write!(f, "{} is not valid UTF-8", name)?,
+            DecodeError::LengthOverflow { name, .. } => write!(f, "{} is too long", name)?,
+            DecodeError::CastOverflow { name, .. } => {
+                write!(f, "{} is out of range for the target type", name)?
+            }
+            DecodeError::ArithmeticOverflow { name, ty, .. } => {
+                let direction = match ty {
+                    ArithmeticOverflow::Overflow => "overflows",
+                    ArithmeticOverflow::Underflow => "underflows",
+                };
+                write!(f, "{} {}", name, direction)?
+            }
+            DecodeError::DepthOverflow { name, limit, .. } => write!(
+                f,
+                "nesting depth exceeds the limit of {} while decoding {}",
+                limit, name
+            )?,
+            DecodeError::LengthMismatch {
+                name,
+                expect,
+                value,
+                ..
+            } => write!(
+                f,
+                "{} has {} elements, but {} were expected",
+                name, value, expect
+            )?,
+            DecodeError::TrailingData { .. } => f.write_str("trailing data after the value")?,
+            DecodeError::IndefiniteSize { .. } => f.write_str("indefinite-length item")?,
+            DecodeError::NonMinimalEncoding { name, found, .. } => write!(
+                f,
+                "{} is not encoded with the minimal number of bytes ({:02x})",
+                name, found
+            )?,
+            DecodeError::UnsortedMapKeys { .. } => f.write_str("map keys are not sorted")?,
+            DecodeError::DuplicateMapKey { .. } => f.write_str("duplicate map key")?,
+            DecodeError::StringTooLong {
+                name, len, limit, ..
+            } => write!(
+                f,
+                "{} of {} bytes exceeds the limit of {} bytes",
+                name, len, limit
+            )?,
+            DecodeError::CollectionTooLong {
+                name, len, limit, ..
+            } => write!(
+                f,
+                "{} of {} elements exceeds the limit of {} elements",
+                name, len, limit
+            )?,
+            DecodeError::TooManyItems { limit, .. } => {
+                write!(f, "the data contains more than {} items", limit)?
+            }
+            DecodeError::AllocationLimitExceeded { limit, .. } => {
+                write!(f, "decoding needs to allocate more than {} bytes", limit)?
+            }
+            DecodeError::InvalidCid { reason, .. } => write!(f, "invalid CID, {}", reason)?,
+        }
+        let location = self.location();
+        if location.offset().is_some() || !location.path().is_empty() {
+            write!(f, " {}", location)?;
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, "; {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes the kind of data item that starts with the given initial byte.
+fn describe(byte: u8) -> &'static str {
+    match byte >> 5 {
+        0 => "unsigned integer",
+        1 => "negative integer",
+        2 => "byte string",
+        3 => "text string",
+        4 => "array",
+        5 => "map",
+        6 => "tag",
+        _ => match byte {
+            0xf4 | 0xf5 => "boolean",
+            0xf6 => "null",
+            0xf7 => "undefined",
+            0xf9 => "half-precision float",
+            0xfa => "single-precision float",
+            0xfb => "double-precision float",
+            0xff => "break",
+            _ => "simple value",
+        },
+    }
+}
+
+/// Returns whether the initial byte is the one of a float.
+fn is_float(byte: u8) -> bool {
+    matches!(byte, 0xf9..=0xfb)
+}
+
+/// The kind of a [`DecodeError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecodeErrorKind {
+    /// See [`DecodeError::Msg`].
+    Msg,
+    /// See [`DecodeError::Read`].
+    Read,
+    /// See [`DecodeError::Eof`].
+    Eof,
+    /// See [`DecodeError::Mismatch`].
+    Mismatch,
+    /// See [`DecodeError::Unsupported`].
+    Unsupported,
+    /// See [`DecodeError::RequireLength`].
+    RequireLength,
+    /// See [`DecodeError::RequireBorrowed`].
+    RequireBorrowed,
+    /// See [`DecodeError::RequireUtf8`].
+    RequireUtf8,
+    /// See [`DecodeError::LengthOverflow`].
+    LengthOverflow,
+    /// See [`DecodeError::CastOverflow`].
+    CastOverflow,
+    /// See [`DecodeError::ArithmeticOverflow`].
+    ArithmeticOverflow,
+    /// See [`DecodeError::DepthOverflow`].
+    DepthOverflow,
+    /// See [`DecodeError::LengthMismatch`].
+    LengthMismatch,
+    /// See [`DecodeError::TrailingData`].
+    TrailingData,
+    /// See [`DecodeError::IndefiniteSize`].
+    IndefiniteSize,
+    /// See [`DecodeError::NonMinimalEncoding`].
+    NonMinimalEncoding,
+    /// See [`DecodeError::UnsortedMapKeys`].
+    UnsortedMapKeys,
+    /// See [`DecodeError::DuplicateMapKey`].
+    DuplicateMapKey,
+    /// See [`DecodeError::StringTooLong`].
+    StringTooLong,
+    /// See [`DecodeError::CollectionTooLong`].
+    CollectionTooLong,
+    /// See [`DecodeError::TooManyItems`].
+    TooManyItems,
+    /// See [`DecodeError::AllocationLimitExceeded`].
+    AllocationLimitExceeded,
+    /// See [`DecodeError::InvalidCid`].
+    InvalidCid,
+}
+
+impl DecodeErrorKind {
+    /// Returns a stable identifier of the kind, e.g. for error responses of an API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecodeErrorKind::Msg => "msg",
+            DecodeErrorKind::Read => "read",
+            DecodeErrorKind::Eof => "eof",
+            DecodeErrorKind::Mismatch => "mismatch",
+            DecodeErrorKind::Unsupported => "unsupported",
+            DecodeErrorKind::RequireLength => "require_length",
+            DecodeErrorKind::RequireBorrowed => "require_borrowed",
+            DecodeErrorKind::RequireUtf8 => "require_utf8",
+            DecodeErrorKind::LengthOverflow => "length_overflow",
+            DecodeErrorKind::CastOverflow => "cast_overflow",
+            DecodeErrorKind::ArithmeticOverflow => "arithmetic_overflow",
+            DecodeErrorKind::DepthOverflow => "depth_overflow",
+            DecodeErrorKind::LengthMismatch => "length_mismatch",
+            DecodeErrorKind::TrailingData => "trailing_data",
+            DecodeErrorKind::IndefiniteSize => "indefinite_size",
+            DecodeErrorKind::NonMinimalEncoding => "non_minimal_encoding",
+            DecodeErrorKind::UnsortedMapKeys => "unsorted_map_keys",
+            DecodeErrorKind::DuplicateMapKey => "duplicate_map_key",
+            DecodeErrorKind::StringTooLong => "string_too_long",
+            DecodeErrorKind::CollectionTooLong => "collection_too_long",
+            DecodeErrorKind::TooManyItems => "too_many_items",
+            DecodeErrorKind::AllocationLimitExceeded => "allocation_limit_exceeded",
+            DecodeErrorKind::InvalidCid => "invalid_cid",
+        }
+    }
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
 impl<E: fmt::Debug> From<cbor4ii::core::error::DecodeError<E>> for DecodeError<E> {
     fn from(err: cbor4ii::core::error::DecodeError<E>) -> DecodeError<E> {
         use cbor4ii::core::error::DecodeError as Cbor4iiError;
+        // The location is filled in by the deserializer.
+        let location = Location::default();
         match err {
-            Cbor4iiError::Read(read) => DecodeError::Read(read),
-            Cbor4iiError::Eof { name, expect } => DecodeError::Eof { name, expect },
-            Cbor4iiError::Mismatch { name, found } => DecodeError::Mismatch { name, found },
-            Cbor4iiError::Unsupported { name, found } => DecodeError::Unsupported { name, found },
-            Cbor4iiError::RequireLength { name, found } => {
-                DecodeError::RequireLength { name, found }
+            Cbor4iiError::Read(read) => DecodeError::Read(read, location),
+            Cbor4iiError::Eof { name, expect } => DecodeError::Eof {
+                name,
+                expect,
+                location,
+            },
+            Cbor4iiError::Mismatch { name, found } => DecodeError::Mismatch {
+                name,
+                found,
+                location,
+            },
+            Cbor4iiError::Unsupported { name, found } => DecodeError::Unsupported {
+                name,
+                found,
+                location,
+            },
+            Cbor4iiError::RequireLength { name, found } => DecodeError::RequireLength {
+                name,
+                found,
+                location,
+            },
+            Cbor4iiError::RequireBorrowed { name } => {
+                DecodeError::RequireBorrowed { name, location }
             }
-            Cbor4iiError::RequireBorrowed { name } => DecodeError::RequireBorrowed { name },
-            Cbor4iiError::RequireUtf8 { name } => DecodeError::RequireUtf8 { name },
-            Cbor4iiError::LengthOverflow { name, found } => {
-                DecodeError::LengthOverflow { name, found }
-            }
-            Cbor4iiError::CastOverflow { name } => DecodeError::CastOverflow { name },
+            Cbor4iiError::RequireUtf8 { name } => DecodeError::RequireUtf8 { name, location },
+            Cbor4iiError::LengthOverflow { name, found } => DecodeError::LengthOverflow {
+                name,
+                found,
+                location,
+            },
+            Cbor4iiError::CastOverflow { name } => DecodeError::CastOverflow { name, location },
             Cbor4iiError::ArithmeticOverflow { name, ty } => {
-                DecodeError::ArithmeticOverflow { name, ty }
+                DecodeError::ArithmeticOverflow { name, ty, location }
             }
-            Cbor4iiError::DepthOverflow { name } => DecodeError::DepthOverflow { name },
             // Future-proof against new upstream variants without an SDK bump; loses structured info
             // but preserves the Display string.
-            _ => DecodeError::Msg(err.to_string()),
+            _ => DecodeError::Msg(err.to_string(), location),
         }
     }
 }
@@ -234,21 +1049,54 @@ pub enum CodecError {
     EncodeIo(EncodeError<std::io::Error>),
 }
 
+impl CodecError {
+    /// Returns the kind of the error, for matching on it without caring about the details.
+    pub fn kind(&self) -> CodecErrorKind {
+        match self {
+            Self::Decode(error) => CodecErrorKind::Decode(error.kind()),
+            Self::Encode(error) => CodecErrorKind::Encode(error.kind()),
+            #[cfg(feature = "std")]
+            Self::DecodeIo(error) => CodecErrorKind::Decode(error.kind()),
+            #[cfg(feature = "std")]
+            Self::EncodeIo(error) => CodecErrorKind::Encode(error.kind()),
+        }
+    }
+}
+
 impl fmt::Display for CodecError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Decode(error) => write!(f, "decode error: {}", error),
-            Self::Encode(error) => write!(f, "encode error: {}", error),
+            Self::Decode(error) => write!(f, "failed to decode DAG-CBOR: {}", error),
+            Self::Encode(error) => write!(f, "failed to encode DAG-CBOR: {}", error),
             #[cfg(feature = "std")]
-            Self::DecodeIo(error) => write!(f, "decode io error: {}", error),
+            Self::DecodeIo(error) => write!(f, "failed to decode DAG-CBOR: {}", error),
             #[cfg(feature = "std")]
-            Self::EncodeIo(error) => write!(f, "encode io error: {}", error),
+            Self::EncodeIo(error) => write!(f, "failed to encode DAG-CBOR: {}", error),
         }
     }
 }
 
 #[cfg(feature = "std")]
-impl std::error::Error for CodecError {}
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(error) => Some(error),
+            Self::Encode(error) => Some(error),
+            Self::DecodeIo(error) => Some(error),
+            Self::EncodeIo(error) => Some(error),
+        }
+    }
+}
+
+/// The kind of a [`CodecError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CodecErrorKind {
+    /// A decoding error of the given kind.
+    Decode(DecodeErrorKind),
+    /// An encoding error of the given kind.
+    Encode(EncodeErrorKind),
+}
 
 impl From<DecodeError<Infallible>> for CodecError {
     fn from(error: DecodeError<Infallible>) -> Self {
@@ -275,3 +1123,38 @@ impl From<EncodeError<std::io::Error>> for CodecError {
         Self::EncodeIo(error)
     }
 }
+
+/// An error while rewriting the links of DAG-CBOR data, see
+/// [`rewrite_links`](crate::links::rewrite_links).
+#[derive(Debug)]
+pub enum RewriteError<E> {
+    /// The input couldn't be decoded.
+    Decode(DecodeError<Infallible>),
+    /// Rewriting a link failed.
+    Rewrite(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RewriteError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(error) => write!(f, "failed to decode DAG-CBOR: {}", error),
+            Self::Rewrite(error) => write!(f, "failed to rewrite link: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for RewriteError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(error) => Some(error),
+            Self::Rewrite(error) => Some(error),
+        }
+    }
+}
+
+impl<E> From<DecodeError<Infallible>> for RewriteError<E> {
+    fn from(error: DecodeError<Infallible>) -> Self {
+        Self::Decode(error)
+    }
+}
@@ -102,6 +102,7 @@
 //! ```
 //!
 //! Without the `std` feature the functions [from_reader], and [to_writer] are not exported.
+//! [to_slice] encodes into a fixed buffer, without allocating.
 //!
 //! *Note*: to use derive macros in serde you will need to declare `serde`
 //! dependency like so:
@@ -114,14 +115,16 @@
 
 extern crate alloc;
 
+pub mod canonical;
 mod cbor4ii_nonpub;
-// The `Codec` implementation is only available if the `no-cid-as-bytes` feature is disabled, due
-// to the links being extracted with a Serde based approach.
-#[cfg(all(feature = "std", not(feature = "no-cid-as-bytes"), feature = "codec"))]
+#[cfg(all(feature = "std", feature = "codec"))]
 pub mod codec;
 pub mod de;
 pub mod error;
+pub mod links;
 pub mod ser;
+mod traverse;
+pub mod validate;
 
 #[doc(inline)]
 pub use crate::error::{DecodeError, EncodeError};
@@ -135,7 +138,7 @@ pub use crate::de::from_slice;
 pub use crate::de::from_reader;
 
 #[doc(inline)]
-pub use crate::ser::to_vec;
+pub use crate::ser::{to_slice, to_vec};
 
 #[cfg(feature = "std")]
 #[doc(inline)]
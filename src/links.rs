@@ -0,0 +1,280 @@
+//! Finding the links (CIDs) within DAG-CBOR data.
+//!
+//! Contrary to decoding the data into [`Ipld`](ipld_core::ipld::Ipld) and searching it, no tree
+//! of values is built. [`walk_links`] reports every link together with the path to it and its
+//! byte offset. [`LinkScanner`] only returns the links, it scans the data for tag 42 without going
+//! through Serde, also incrementally from a reader. [`rewrite_links`] replaces the links, while
+//! copying everything else unchanged.
+//!
+//! # Examples
+//!
+//! ```
+//! # use std::collections::BTreeMap;
+//! # use std::str::FromStr;
+//! # use ipld_core::{cid::Cid, ipld::Ipld};
+//! use serde_ipld_dagcbor::{links, to_vec};
+//!
+//! let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+//! let mut map = BTreeMap::new();
+//! map.insert("parents".to_string(), Ipld::List(vec![Ipld::Null, Ipld::Link(cid)]));
+//! let encoded = to_vec(&Ipld::Map(map)).unwrap();
+//!
+//! let mut found = Vec::new();
+//! links::walk_links(&encoded, |path, cid, offset| {
+//!     found.push((path.to_string(), cid, offset));
+//! })
+//! .unwrap();
+//! assert_eq!(found, [("parents[1]".to_string(), cid, 11)]);
+//! ```
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use cbor4ii::core::{dec, utils::SliceReader};
+use ipld_core::cid::Cid;
+
+use crate::de::{Deserializer, DeserializerOptions, LinkCursor};
+use crate::error::{DecodeError, Path, RewriteError};
+use crate::traverse::{traverse, Visit};
+use crate::CBOR_TAGS_CID;
+#[cfg(feature = "std")]
+use cbor4ii::core::utils::IoReader;
+
+/// Calls `visit` for every link within a single DAG-CBOR value.
+///
+/// Besides the CID, `visit` gets the path to the link, e.g. `entries[12].header.parents[0]`, and
+/// the byte offset of its tag. The data is decoded with the default options, see
+/// [`walk_links_with`] for other ones.
+pub fn walk_links<F>(buf: &[u8], visit: F) -> Result<(), DecodeError<Infallible>>
+where
+    F: FnMut(&Path, Cid, usize),
+{
+    walk_links_with(buf, DeserializerOptions::default(), visit)
+}
+
+/// Calls `visit` for every link within a single DAG-CBOR value, which is decoded with the given
+/// options.
+///
+/// The options apply just like when decoding the data into a value, e.g. with
+/// [`DeserializerOptions::strict`] the links of invalid DAG-CBOR aren't reported completely, as
+/// the first violation is returned as error. See [`walk_links`] for details.
+pub fn walk_links_with<F>(
+    buf: &[u8],
+    options: DeserializerOptions,
+    mut visit: F,
+) -> Result<(), DecodeError<Infallible>>
+where
+    F: FnMut(&Path, Cid, usize),
+{
+    run(
+        Deserializer::from_slice(buf).with_options(options),
+        |path, cid, range| visit(path, cid, range.start),
+    )
+}
+
+/// Returns a copy of a single DAG-CBOR value, with every link replaced by the result of `rewrite`.
+///
+/// Everything besides the links is copied byte for byte, only the lengths of the byte strings of
+/// replaced CIDs change. As map keys are strings, their order stays the same. Links that
+/// `rewrite` returns unchanged are copied as they are, too. The data is decoded with the default
+/// options, see [`rewrite_links_with`] for other ones.
+///
+/// # Examples
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use ipld_core::{cid::Cid, ipld::Ipld};
+/// use serde_ipld_dagcbor::{from_slice, links, to_vec};
+///
+/// // Upgrade a CIDv0 to a CIDv1.
+/// let cid = Cid::from_str("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+/// let encoded = to_vec(&Ipld::List(vec![Ipld::Link(cid), Ipld::Bool(true)])).unwrap();
+///
+/// let rewritten = links::rewrite_links(&encoded, |cid| cid.into_v1()).unwrap();
+/// let decoded: Ipld = from_slice(&rewritten).unwrap();
+/// assert_eq!(decoded, Ipld::List(vec![Ipld::Link(cid.into_v1().unwrap()), Ipld::Bool(true)]));
+/// ```
+pub fn rewrite_links<F, E>(buf: &[u8], rewrite: F) -> Result<Vec<u8>, RewriteError<E>>
+where
+    F: FnMut(&Cid) -> Result<Cid, E>,
+{
+    rewrite_links_with(buf, DeserializerOptions::default(), rewrite)
+}
+
+/// Returns a copy of a single DAG-CBOR value, which is decoded with the given options, with every
+/// link replaced by the result of `rewrite`.
+///
+/// See [`rewrite_links`] for details. Deviations from DAG-CBOR that the options accept are
+/// copied unchanged, e.g. with [`DeserializerOptions::lenient`] the output might not be valid
+/// DAG-CBOR.
+pub fn rewrite_links_with<F, E>(
+    buf: &[u8],
+    options: DeserializerOptions,
+    mut rewrite: F,
+) -> Result<Vec<u8>, RewriteError<E>>
+where
+    F: FnMut(&Cid) -> Result<Cid, E>,
+{
+    let mut output = Vec::with_capacity(buf.len());
+    // The input up to `copied` is in the output already.
+    let mut copied = 0;
+    let mut rewrite_error = None;
+    run(
+        Deserializer::from_slice(buf).with_options(options),
+        |_, cid, range| {
+            // After a failed rewrite, the rest of the input is only checked.
+            if rewrite_error.is_some() {
+                return;
+            }
+            match rewrite(&cid) {
+                Ok(new) => {
+                    output.extend_from_slice(&buf[copied..range.start]);
+                    if new == cid {
+                        output.extend_from_slice(&buf[range.clone()]);
+                    } else {
+                        push_link(&mut output, &new);
+                    }
+                    copied = range.end;
+                }
+                Err(error) => rewrite_error = Some(error),
+            }
+        },
+    )?;
+    if let Some(error) = rewrite_error {
+        return Err(RewriteError::Rewrite(error));
+    }
+    output.extend_from_slice(&buf[copied..]);
+    Ok(output)
+}
+
+/// Appends a link, encoded like the serializer does.
+fn push_link(output: &mut Vec<u8>, cid: &Cid) {
+    let bytes = cid.to_bytes();
+    // The bytes of the CID are prefixed with a null byte.
+    let len = bytes.len() + 1;
+    output.extend_from_slice(&[0xd8, CBOR_TAGS_CID]);
+    if len < 24 {
+        output.push(0x40 | len as u8);
+    } else if len <= 0xff {
+        output.extend_from_slice(&[0x58, len as u8]);
+    } else {
+        // CIDs are always shorter than 64 KiB.
+        output.push(0x59);
+        output.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+    output.push(0x00);
+    output.extend_from_slice(&bytes);
+}
+
+/// Walks a single value and calls `visit` with the range of the input that every link spans.
+fn run<'de, R, F>(mut de: Deserializer<R>, visit: F) -> Result<(), DecodeError<R::Error>>
+where
+    R: dec::Read<'de>,
+    F: FnMut(&Path, Cid, Range<usize>),
+{
+    traverse(&mut de, &mut LinkCallback(visit))?;
+    de.end()
+}
+
+/// An iterator over the links within a single DAG-CBOR value.
+///
+/// The data is checked like when decoding it with the given options, except that:
+///  - the order of map keys isn't checked
+///  - the UTF-8 of strings other than map keys isn't validated
+///  - the nesting depth isn't limited
+///  - items of indefinite length are always rejected
+///
+/// Trailing data is an error, which is returned after the last link.
+///
+/// # Examples
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use ipld_core::{cid::Cid, ipld::Ipld};
+/// use serde_ipld_dagcbor::{links::LinkScanner, to_vec};
+///
+/// let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+/// let encoded = to_vec(&Ipld::List(vec![Ipld::Link(cid), Ipld::Null, Ipld::Link(cid)])).unwrap();
+///
+/// let links = LinkScanner::from_slice(&encoded).collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(links, [cid, cid]);
+/// ```
+#[derive(Debug)]
+pub struct LinkScanner<'de, R> {
+    de: Deserializer<R>,
+    /// The position within the value.
+    cursor: LinkCursor,
+    /// Whether the end of the value was reached or an error was returned.
+    done: bool,
+    lifetime: PhantomData<&'de ()>,
+}
+
+impl<'a> LinkScanner<'a, SliceReader<'a>> {
+    /// Scans a slice for links.
+    pub fn from_slice(buf: &'a [u8]) -> Self {
+        Self {
+            de: Deserializer::from_slice(buf),
+            cursor: LinkCursor::new(),
+            done: false,
+            lifetime: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::BufRead> LinkScanner<'_, IoReader<R>> {
+    /// Scans a reader for links.
+    ///
+    /// The links are returned as soon as they are read, the data around them is skipped without
+    /// allocating. The reader must end after the value, as trailing data is an error. To scan a
+    /// single block of a larger stream, limit the reader with [`std::io::Read::take`].
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            de: Deserializer::from_reader(IoReader::new(reader)),
+            cursor: LinkCursor::new(),
+            done: false,
+            lifetime: PhantomData,
+        }
+    }
+}
+
+impl<R> LinkScanner<'_, R> {
+    /// Sets the options that are used for checking the data.
+    pub fn with_options(mut self, options: DeserializerOptions) -> Self {
+        self.de = self.de.with_options(options);
+        self
+    }
+}
+
+impl<'de, R: dec::Read<'de>> Iterator for LinkScanner<'de, R> {
+    type Item = Result<Cid, DecodeError<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.de.next_link(&mut self.cursor) {
+            Ok(Some((_, cid))) => Some(Ok(cid)),
+            Ok(None) => {
+                self.done = true;
+                self.de.end().err().map(Err)
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(self.de.locate(error)))
+            }
+        }
+    }
+}
+
+/// Passes the links of a traversal to a callback.
+struct LinkCallback<F>(F);
+
+impl<F: FnMut(&Path, Cid, Range<usize>)> Visit for LinkCallback<F> {
+    const TRACK_PATH: bool = true;
+
+    fn link(&mut self, path: &Path, cid: Cid, range: Range<usize>) {
+        (self.0)(path, cid, range)
+    }
+}
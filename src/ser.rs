@@ -1,21 +1,30 @@
 //! Serialization.
 #[cfg(not(feature = "std"))]
-use alloc::{collections::TryReserveError, string::ToString, vec::Vec};
+use alloc::{collections::TryReserveError, vec::Vec};
 #[cfg(feature = "std")]
 use std::collections::TryReserveError;
 
 pub use cbor4ii::core::utils::BufWriter;
 #[cfg(feature = "std")]
 use cbor4ii::core::utils::IoWriter;
+use core::{
+    cmp::Ordering,
+    convert::Infallible,
+    fmt::{self, Write as _},
+    mem,
+    ops::Range,
+};
+
 use cbor4ii::core::{
-    dec,
+    dec::{self, Decode},
     enc::{self, Encode},
     major, types,
+    utils::SliceReader,
 };
 use ipld_core::cid::serde::CID_SERDE_PRIVATE_IDENTIFIER;
 use serde::{ser, Serialize};
 
-use crate::error::EncodeError;
+use crate::error::{BufferFull, EncodeError, Path, PathSegment};
 use crate::CBOR_TAGS_CID;
 
 /// Serializes a value to a vector.
@@ -23,10 +32,261 @@ pub fn to_vec<T>(value: &T) -> Result<Vec<u8>, EncodeError<TryReserveError>>
 where
     T: Serialize + ?Sized,
 {
-    let writer = BufWriter::new(Vec::new());
-    let mut serializer = Serializer::new(writer);
+    to_vec_with(value, SerializerOptions::default())
+}
+
+/// Serializes a value to a vector with the given options.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use serde_ipld_dagcbor::{ser, EncodeError};
+/// let mut map = BTreeMap::new();
+/// map.insert(1, "one");
+/// let options = ser::SerializerOptions::new().stringify_integer_keys(true);
+/// assert_eq!(ser::to_vec_with(&map, options).unwrap(), b"\xa1\x611\x63one");
+///
+/// let options = ser::SerializerOptions::new().max_output_size(4);
+/// let result = ser::to_vec_with("too long", options);
+/// assert!(matches!(result, Err(EncodeError::OutputTooLarge { limit: 4, .. })));
+/// ```
+pub fn to_vec_with<T>(
+    value: &T,
+    options: SerializerOptions,
+) -> Result<Vec<u8>, EncodeError<TryReserveError>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut buf = Vec::new();
+    encode_into(&mut buf, value, options, &mut Arena::default())?;
+    Ok(buf)
+}
+
+/// Serializes a value and appends it to a vector.
+///
+/// If an error occurs, the vector is truncated to its original length. See [`Encoder`] for
+/// encoding many values without allocating.
+///
+/// # Examples
+///
+/// ```
+/// # use serde_ipld_dagcbor::ser;
+/// let mut buf = b"\x82".to_vec();
+/// ser::to_vec_into(&mut buf, "a").unwrap();
+/// ser::to_vec_into(&mut buf, &1).unwrap();
+/// assert_eq!(buf, b"\x82\x61a\x01");
+/// ```
+pub fn to_vec_into<T>(buf: &mut Vec<u8>, value: &T) -> Result<(), EncodeError<TryReserveError>>
+where
+    T: Serialize + ?Sized,
+{
+    encode_into(
+        buf,
+        value,
+        SerializerOptions::default(),
+        &mut Arena::default(),
+    )
+}
+
+/// Appends the encoded value to `buf`, buffering containers in `arena`.
+fn encode_into<T>(
+    buf: &mut Vec<u8>,
+    value: &T,
+    options: SerializerOptions,
+    arena: &mut Arena,
+) -> Result<(), EncodeError<TryReserveError>>
+where
+    T: Serialize + ?Sized,
+{
+    let start = buf.len();
+    let writer = BufWriter::new(mem::take(buf));
+    let mut serializer = Serializer::new(writer)
+        .with_options(options)
+        .with_backpatching();
+    mem::swap(&mut serializer.arena, arena);
+    let result = value.serialize(&mut serializer);
+    mem::swap(&mut serializer.arena, arena);
+    // After an error, the arena may still contain buffered output.
+    arena.clear();
+    *buf = serializer.into_inner().into_inner();
+    if result.is_err() {
+        buf.truncate(start);
+    }
+    result
+}
+
+/// An encoder that keeps its scratch space between values.
+///
+/// Maps and sequences of unknown length are buffered while they are encoded. Contrary to
+/// [`to_vec`], the encoder keeps the memory for that, so once it has grown large enough, encoding
+/// into a vector with enough capacity doesn't allocate. The memory is only freed when the encoder
+/// is dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use serde_ipld_dagcbor::ser::Encoder;
+/// let mut encoder = Encoder::new();
+/// let mut buf = Vec::new();
+/// for i in 0..3u8 {
+///     let mut record = BTreeMap::new();
+///     record.insert("index", i);
+///     buf.clear();
+///     encoder.encode_into(&mut buf, &record).unwrap();
+///     assert_eq!(buf, [&b"\xa1\x65index"[..], &[i]].concat());
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Encoder {
+    options: SerializerOptions,
+    arena: Arena,
+}
+
+impl Encoder {
+    /// Creates an encoder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an encoder with the given options.
+    pub fn with_options(options: SerializerOptions) -> Self {
+        Self {
+            options,
+            arena: Arena::default(),
+        }
+    }
+
+    /// Returns the options that are used for encoding.
+    pub fn options(&self) -> SerializerOptions {
+        self.options
+    }
+
+    /// Serializes a value and appends it to a vector.
+    ///
+    /// If an error occurs, the vector is truncated to its original length.
+    pub fn encode_into<T>(
+        &mut self,
+        buf: &mut Vec<u8>,
+        value: &T,
+    ) -> Result<(), EncodeError<TryReserveError>>
+    where
+        T: Serialize + ?Sized,
+    {
+        encode_into(buf, value, self.options, &mut self.arena)
+    }
+
+    /// Serializes a value to a new vector.
+    pub fn to_vec<T>(&mut self, value: &T) -> Result<Vec<u8>, EncodeError<TryReserveError>>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf, value)?;
+        Ok(buf)
+    }
+}
+
+/// Returns the length of a value encoded as DAG-CBOR, without encoding it.
+///
+/// It fails if [`to_vec`] would fail. It's considerably cheaper than encoding though: map entries
+/// don't need to be sorted, as the order doesn't change the length, and sequences of unknown
+/// length don't need to be buffered. Only map keys are buffered, in order to check them.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use serde_ipld_dagcbor::{ser, to_vec};
+/// let mut map = BTreeMap::new();
+/// map.insert("key", vec![1, 2, 3]);
+/// assert_eq!(ser::encoded_len(&map).unwrap(), to_vec(&map).unwrap().len());
+/// ```
+pub fn encoded_len<T>(value: &T) -> Result<usize, EncodeError<Infallible>>
+where
+    T: Serialize + ?Sized,
+{
+    encoded_len_with(value, SerializerOptions::default())
+}
+
+/// Returns the length of a value encoded as DAG-CBOR with the given options, without encoding it.
+///
+/// It fails if [`to_vec_with`] would fail with the same options, see [`encoded_len`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use serde_ipld_dagcbor::ser;
+/// let mut map = BTreeMap::new();
+/// map.insert(10, "ten");
+/// let options = ser::SerializerOptions::new().stringify_integer_keys(true);
+/// assert_eq!(ser::encoded_len_with(&map, options).unwrap(), 8);
+/// ```
+pub fn encoded_len_with<T>(
+    value: &T,
+    options: SerializerOptions,
+) -> Result<usize, EncodeError<Infallible>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::new(Sink).with_options(options);
+    serializer.counting = true;
+    value.serialize(&mut serializer)?;
+    Ok(serializer.written)
+}
+
+/// Serializes a value into a slice and returns the number of bytes written.
+///
+/// Nothing is allocated, unless an error occurs, so this works on targets without a heap too:
+/// maps are sorted within the slice and the headers of sequences of unknown length are moved in
+/// front of their elements. Sorting a map takes quadratic time in its number of entries though,
+/// unless they are mostly in order already.
+///
+/// If the slice is too small, [`EncodeError::Write`] with [`BufferFull`] is returned and the
+/// contents of the slice are unspecified.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use serde_ipld_dagcbor::{error::BufferFull, ser, EncodeError};
+/// let mut map = BTreeMap::new();
+/// map.insert("bb", 1);
+/// map.insert("a", 2);
+/// let mut buf = [0; 16];
+/// let len = ser::to_slice(&mut buf, &map).unwrap();
+/// assert_eq!(&buf[..len], b"\xa2\x61a\x02\x62bb\x01");
+///
+/// let result = ser::to_slice(&mut buf[..4], &map);
+/// assert!(matches!(result, Err(EncodeError::Write(BufferFull))));
+/// ```
+pub fn to_slice<T>(buf: &mut [u8], value: &T) -> Result<usize, EncodeError<BufferFull>>
+where
+    T: Serialize + ?Sized,
+{
+    to_slice_with(buf, value, SerializerOptions::default())
+}
+
+/// Serializes a value into a slice with the given options and returns the number of bytes
+/// written.
+///
+/// See [`to_slice`] for details.
+pub fn to_slice_with<T>(
+    buf: &mut [u8],
+    value: &T,
+    options: SerializerOptions,
+) -> Result<usize, EncodeError<BufferFull>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::new(SliceWriter { buf, len: 0 })
+        .with_options(options)
+        .with_backpatching();
+    serializer.tail_mut = Some(SliceWriter::tail_mut);
     value.serialize(&mut serializer)?;
-    Ok(serializer.into_inner().into_inner())
+    Ok(serializer.written)
 }
 
 /// Serializes a value to a writer.
@@ -36,19 +296,183 @@ where
     W: std::io::Write,
     T: Serialize,
 {
-    let mut serializer = Serializer::new(IoWriter::new(writer));
+    to_writer_with(writer, value, SerializerOptions::default())
+}
+
+/// Serializes a value to a writer with the given options.
+///
+/// If the maximum output size is exceeded, the output written so far is incomplete.
+#[cfg(feature = "std")]
+pub fn to_writer_with<W, T>(
+    writer: W,
+    value: &T,
+    options: SerializerOptions,
+) -> Result<(), EncodeError<std::io::Error>>
+where
+    W: std::io::Write,
+    T: Serialize,
+{
+    let mut serializer = Serializer::new(IoWriter::new(writer)).with_options(options);
+    value.serialize(&mut serializer)
+}
+
+/// Serializes a value to a writer that can seek and read back what was written, like a file.
+///
+/// Contrary to [`to_writer`], sequences of unknown length aren't buffered in memory, see
+/// [`SeekWriter`].
+#[cfg(feature = "std")]
+pub fn to_seekable_writer<W, T>(writer: W, value: &T) -> Result<(), EncodeError<std::io::Error>>
+where
+    W: std::io::Read + std::io::Write + std::io::Seek,
+    T: Serialize,
+{
+    to_seekable_writer_with(writer, value, SerializerOptions::default())
+}
+
+/// Serializes a value to a writer that can seek and read back what was written with the given
+/// options.
+///
+/// If the maximum output size is exceeded, the output written so far is incomplete.
+#[cfg(feature = "std")]
+pub fn to_seekable_writer_with<W, T>(
+    writer: W,
+    value: &T,
+    options: SerializerOptions,
+) -> Result<(), EncodeError<std::io::Error>>
+where
+    W: std::io::Read + std::io::Write + std::io::Seek,
+    T: Serialize,
+{
+    let mut serializer = Serializer::new(SeekWriter::new(writer))
+        .with_options(options)
+        .with_backpatching();
     value.serialize(&mut serializer)
 }
 
+/// Options that control how values are encoded.
+///
+/// The defaults encode everything that can be represented in DAG-CBOR, without any limits. Some
+/// policies are fixed, as DAG-CBOR leaves no choice:
+///  - floats are encoded with 64 bits, NaN and Infinity are rejected
+///  - units and `None` are encoded as null
+///  - map keys must be strings, maps are sorted canonically
+///  - the length of sequences of unknown length is encoded upfront: [`to_vec`], [`to_slice`] and
+///    `to_seekable_writer` patch it in front of the elements once they are written, while
+///    `to_writer` and a [`Serializer`] without backpatching buffer the elements in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SerializerOptions {
+    max_output_size: usize,
+    max_depth: usize,
+    stringify_integer_keys: bool,
+    check_duplicate_keys: bool,
+}
+
+impl Default for SerializerOptions {
+    fn default() -> Self {
+        Self {
+            max_output_size: usize::MAX,
+            max_depth: usize::MAX,
+            stringify_integer_keys: false,
+            check_duplicate_keys: true,
+        }
+    }
+}
+
+impl SerializerOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum size of the output in bytes.
+    ///
+    /// Exceeding it results in [`EncodeError::OutputTooLarge`], without the output beyond the limit
+    /// being written. There is no limit by default.
+    pub fn max_output_size(mut self, max_output_size: usize) -> Self {
+        self.max_output_size = max_output_size;
+        self
+    }
+
+    /// Sets the maximum nesting depth of the encoded data.
+    ///
+    /// Every array and map counts as one level, the map that wraps an enum variant with data
+    /// doesn't. Deeper data is rejected with [`EncodeError::DepthOverflow`]. There is no limit by
+    /// default.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets whether integer map keys are encoded as strings of their decimal representation.
+    ///
+    /// This allows encoding maps like `BTreeMap<u32, T>`. It is disabled by default, where
+    /// integer keys are rejected like all other keys that aren't strings.
+    pub fn stringify_integer_keys(mut self, stringify: bool) -> Self {
+        self.stringify_integer_keys = stringify;
+        self
+    }
+
+    /// Sets whether maps are checked for duplicate keys.
+    ///
+    /// Duplicate keys can be produced by custom `Serialize` implementations, or when keys are
+    /// stringified, and result in data that isn't valid DAG-CBOR. If enabled, they are rejected
+    /// with [`EncodeError::DuplicateMapKey`]. It is enabled by default.
+    pub fn check_duplicate_keys(mut self, check: bool) -> Self {
+        self.check_duplicate_keys = check;
+        self
+    }
+}
+
 /// A structure for serializing Rust values to DAG-CBOR.
 pub struct Serializer<W> {
     writer: W,
+    options: SerializerOptions,
+    /// The current nesting depth.
+    depth: usize,
+    /// The number of bytes of output produced so far, including the ones that are still buffered.
+    written: usize,
+    /// The output of the containers that are currently buffered.
+    arena: Arena,
+    /// Moves output of the writer, if it supports it, see [`Backpatch`].
+    backpatch: Option<fn(&mut W, usize, usize)>,
+    /// Whether a sequence of unknown length is currently backpatched.
+    backpatching: bool,
+    /// Returns the given number of bytes of output that were written last, if the writer supports
+    /// it. Containers are then encoded in place, without buffering them.
+    tail_mut: Option<for<'a> fn(&'a mut W, usize) -> &'a mut [u8]>,
+    /// Whether the output is only counted. Only map keys are buffered then, in order to check
+    /// and sort them.
+    counting: bool,
+    /// Whether a map key is currently serialized.
+    in_key: bool,
 }
 
 impl<W> Serializer<W> {
     /// Creates a new CBOR serializer.
     pub fn new(writer: W) -> Serializer<W> {
-        Serializer { writer }
+        Serializer {
+            writer,
+            options: SerializerOptions::default(),
+            depth: 0,
+            written: 0,
+            arena: Arena::default(),
+            backpatch: None,
+            backpatching: false,
+            tail_mut: None,
+            counting: false,
+            in_key: false,
+        }
+    }
+
+    /// Sets the options that are used for encoding.
+    pub fn with_options(mut self, options: SerializerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the options that are used for encoding.
+    pub fn options(&self) -> SerializerOptions {
+        self.options
     }
 
     /// Returns the underlying writer.
@@ -57,6 +481,552 @@ impl<W> Serializer<W> {
     }
 }
 
+impl<W: Backpatch> Serializer<W> {
+    /// Writes sequences of unknown length directly to the writer.
+    ///
+    /// By default they are buffered, in order to encode their length before their elements. With
+    /// backpatching, the length is encoded after the elements and then moved in front of them,
+    /// see [`Backpatch`].
+    pub fn with_backpatching(mut self) -> Self {
+        self.backpatch = Some(W::backpatch);
+        self
+    }
+}
+
+impl<W: enc::Write> Serializer<W> {
+    /// Writes the output of `encode`, as long as it stays within the maximum output size.
+    ///
+    /// While a container is buffered, the output goes to the arena instead of the writer.
+    fn write<F>(&mut self, encode: F) -> Result<(), EncodeError<W::Error>>
+    where
+        F: FnOnce(&mut LimitedWriter<'_, W>) -> Result<(), enc::Error<W::Error>>,
+    {
+        let buffer = if self.arena.depth > 0 && (self.in_key || !self.counting) {
+            Some(&mut self.arena.bytes)
+        } else {
+            None
+        };
+        let mut writer = LimitedWriter {
+            writer: &mut self.writer,
+            buffer,
+            written: &mut self.written,
+            limit: self.options.max_output_size,
+            exceeded: false,
+            failed: None,
+        };
+        encode(&mut writer)?;
+        if let Some(error) = writer.failed {
+            Err(EncodeError::AllocationFailed {
+                error,
+                path: Path::default(),
+            })
+        } else if writer.exceeded {
+            Err(EncodeError::OutputTooLarge {
+                limit: self.options.max_output_size,
+                path: Path::default(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Increases the nesting depth, when an array or map is started.
+    fn enter(&mut self) -> Result<(), EncodeError<W::Error>> {
+        if self.depth < self.options.max_depth {
+            self.depth += 1;
+            Ok(())
+        } else {
+            Err(EncodeError::DepthOverflow {
+                limit: self.options.max_depth,
+                path: Path::default(),
+            })
+        }
+    }
+
+    /// Decreases the nesting depth, when an array or map is finished.
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Serializes a map key, which is buffered even if the output is only counted.
+    fn serialize_key<T: Serialize + ?Sized>(
+        &mut self,
+        key: &T,
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.in_key = true;
+        let result = key.serialize(&mut *self);
+        self.in_key = false;
+        result
+    }
+
+    /// Returns where the next output starts: in the arena while a container is buffered,
+    /// otherwise in the writer.
+    fn position(&self) -> usize {
+        if self.arena.depth > 0 {
+            self.arena.bytes.len()
+        } else {
+            self.written
+        }
+    }
+
+    /// Returns the output from the given [`position`](Self::position) on.
+    ///
+    /// Output that isn't buffered is only available when encoding in place.
+    fn output_from(&mut self, position: usize) -> &mut [u8] {
+        match self.tail_mut {
+            Some(tail_mut) if self.arena.depth == 0 => {
+                tail_mut(&mut self.writer, self.written - position)
+            }
+            _ => &mut self.arena.bytes[position..],
+        }
+    }
+
+    /// Moves the header that was just written in front of the output since `start`, which is
+    /// `tail` bytes long.
+    fn move_header(&mut self, start: usize, tail: usize) -> Result<(), EncodeError<W::Error>> {
+        if let Some(backpatch) = self.backpatch {
+            backpatch(&mut self.writer, tail, self.written - start - tail);
+        }
+        // Errors of backpatching are returned by the next write.
+        self.writer.push(&[])?;
+        Ok(())
+    }
+
+    /// Starts buffering a container and returns the index of its first segment in the arena.
+    fn begin_buffered(&mut self) -> usize {
+        self.arena.depth += 1;
+        self.arena.close()
+    }
+
+    /// Finishes buffering a container. If it's the outermost one, the arena is written out.
+    fn end_buffered(&mut self) -> Result<(), EncodeError<W::Error>> {
+        self.arena.depth -= 1;
+        if self.arena.depth > 0 {
+            return Ok(());
+        }
+
+        self.arena.close();
+        // Segments that are still adjacent are written at once.
+        let mut pending = 0..0;
+        for segment in self.arena.segments.drain(..) {
+            if pending.end == segment.start {
+                pending.end = segment.end;
+            } else {
+                self.writer.push(&self.arena.bytes[pending])?;
+                pending = segment;
+            }
+        }
+        self.writer.push(&self.arena.bytes[pending])?;
+        self.arena.clear();
+        Ok(())
+    }
+}
+
+/// Writers that can move output they already wrote.
+///
+/// This allows writing sequences of unknown length without buffering them, see
+/// [`Serializer::with_backpatching`]. Their elements are written directly, followed by their
+/// header, which is then moved in front of them. The header is usually a single byte, so it's
+/// rarely longer than needed, and nothing is left behind in writers that can't be truncated.
+pub trait Backpatch: enc::Write {
+    /// Moves the last `len` bytes that were written in front of the `tail` bytes before them.
+    ///
+    /// Errors have to be returned by the next call to [`enc::Write::push`].
+    fn backpatch(&mut self, tail: usize, len: usize);
+}
+
+impl Backpatch for BufWriter {
+    fn backpatch(&mut self, tail: usize, len: usize) {
+        // The buffer can only be modified after taking it out of the writer, which doesn't
+        // allocate.
+        let mut buffer = mem::replace(self, BufWriter::new(Vec::new())).into_inner();
+        let start = buffer.len() - len - tail;
+        buffer[start..].rotate_right(len);
+        *self = BufWriter::new(buffer);
+    }
+}
+
+/// A writer into a fixed slice.
+///
+/// As the output can be modified after it was written, containers are encoded in place, without
+/// buffering them: maps are sorted within the slice and sequences of unknown length are always
+/// backpatched, even when nested.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    /// The number of bytes written.
+    len: usize,
+}
+
+impl enc::Write for SliceWriter<'_> {
+    type Error = BufferFull;
+
+    fn push(&mut self, input: &[u8]) -> Result<(), Self::Error> {
+        let end = self.len + input.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(BufferFull)?
+            .copy_from_slice(input);
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl Backpatch for SliceWriter<'_> {
+    fn backpatch(&mut self, tail: usize, len: usize) {
+        self.buf[self.len - len - tail..self.len].rotate_right(len);
+    }
+}
+
+impl SliceWriter<'_> {
+    /// Returns the last `len` bytes that were written.
+    fn tail_mut(&mut self, len: usize) -> &mut [u8] {
+        &mut self.buf[self.len - len..self.len]
+    }
+}
+
+/// A writer to work with [`std::io::Write`]rs that can also seek and read back what was written,
+/// like files.
+///
+/// Sequences of unknown length are written directly and then moved in order to insert their
+/// header, see [`Backpatch`]. This happens with short sequences too, as the header is usually a
+/// single byte.
+#[cfg(feature = "std")]
+pub struct SeekWriter<W> {
+    writer: W,
+    /// The error that occurred while backpatching.
+    error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W> SeekWriter<W> {
+    /// Creates a new writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> enc::Write for SeekWriter<W> {
+    type Error = std::io::Error;
+
+    fn push(&mut self, input: &[u8]) -> Result<(), Self::Error> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.write_all(input)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Read + std::io::Write + std::io::Seek> SeekWriter<W> {
+    fn try_backpatch(&mut self, tail: usize, len: usize) -> std::io::Result<()> {
+        use std::io::SeekFrom;
+
+        let end = self.writer.stream_position()?;
+        let start = end - (len + tail) as u64;
+        let mut moved = [0; 9];
+        let moved = &mut moved[..len];
+        self.writer.seek(SeekFrom::Start(end - len as u64))?;
+        self.writer.read_exact(moved)?;
+        // The tail is moved back to front, so that no byte is overwritten before it was moved.
+        let mut buffer = [0; 4096];
+        let mut remaining = tail;
+        while remaining > 0 {
+            let chunk = remaining.min(buffer.len());
+            let from = start + (remaining - chunk) as u64;
+            self.writer.seek(SeekFrom::Start(from))?;
+            self.writer.read_exact(&mut buffer[..chunk])?;
+            self.writer.seek(SeekFrom::Start(from + len as u64))?;
+            self.writer.write_all(&buffer[..chunk])?;
+            remaining -= chunk;
+        }
+        self.writer.seek(SeekFrom::Start(start))?;
+        self.writer.write_all(moved)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Read + std::io::Write + std::io::Seek> Backpatch for SeekWriter<W> {
+    fn backpatch(&mut self, tail: usize, len: usize) {
+        if self.error.is_none() {
+            self.error = self.try_backpatch(tail, len).err();
+        }
+    }
+}
+
+/// Scratch space for the output of containers that can't be written directly, as their entries
+/// need to be sorted (maps) or their length is only known at the end (sequences of unknown
+/// length).
+///
+/// The output of all nested containers is written to a single buffer, each byte once. Sorting the
+/// entries of a map only moves the ranges that describe which bytes are output in which order.
+/// Once the outermost buffered container is finished, those ranges are written out.
+#[derive(Debug, Default)]
+struct Arena {
+    /// The buffered output, in the order it was produced.
+    bytes: Vec<u8>,
+    /// The ranges of `bytes` that make up the output, in the order they will be written.
+    segments: Vec<Range<usize>>,
+    /// Where the range of `bytes` starts that isn't part of `segments` yet.
+    open: usize,
+    /// The entries of all maps that are currently buffered, as ranges of `segments`.
+    entries: Vec<Range<usize>>,
+    /// Scratch space for reordering `segments`.
+    scratch: Vec<Range<usize>>,
+    /// The number of containers that are currently buffered.
+    depth: usize,
+}
+
+impl Arena {
+    /// Ends the current segment and returns the number of segments.
+    fn close(&mut self) -> usize {
+        if self.open < self.bytes.len() {
+            self.segments.push(self.open..self.bytes.len());
+            self.open = self.bytes.len();
+        }
+        self.segments.len()
+    }
+
+    /// Sorts the entries of a map, from `first_entry` on, by their keys.
+    fn sort_entries(&mut self, first_entry: usize) {
+        let (bytes, segments) = (&self.bytes, &self.segments);
+        self.entries[first_entry..]
+            .sort_by(|a, b| entry_key(bytes, segments, a).cmp(entry_key(bytes, segments, b)));
+    }
+
+    /// Returns the first key of the map, from `first_entry` on, that is equal to the next one.
+    ///
+    /// The entries need to be sorted.
+    fn duplicate_key(&self, first_entry: usize) -> Option<&[u8]> {
+        self.entries[first_entry..].windows(2).find_map(|pair| {
+            let key = entry_key(&self.bytes, &self.segments, &pair[0]);
+            (key == entry_key(&self.bytes, &self.segments, &pair[1])).then_some(key)
+        })
+    }
+
+    /// Puts the segments of a map, from `first_segment` on, into their final order.
+    ///
+    /// The last segment is the header of the map, it's followed by the entries from `first_entry`
+    /// on, in their order.
+    fn reorder_entries(&mut self, first_segment: usize, first_entry: usize) {
+        let header = self.close() - 1;
+        self.scratch.push(self.segments[header].clone());
+        for entry in self.entries.drain(first_entry..) {
+            self.scratch.extend_from_slice(&self.segments[entry]);
+        }
+        self.segments.truncate(first_segment);
+        self.segments.append(&mut self.scratch);
+    }
+
+    /// Moves the last segment, which is the header of a sequence, in front of the sequence's
+    /// elements, which start at `first_segment`.
+    ///
+    /// The elements need to be closed before the header is written.
+    fn prepend_header(&mut self, first_segment: usize) {
+        self.close();
+        self.segments[first_segment..].rotate_right(1);
+    }
+
+    /// Drops the segments from `first_segment` on and the entries from `first_entry` on, including
+    /// their bytes.
+    fn discard(&mut self, first_segment: usize, first_entry: usize) {
+        let start = self
+            .segments
+            .get(first_segment)
+            .map_or(self.open, |segment| segment.start);
+        self.segments.truncate(first_segment);
+        self.entries.truncate(first_entry);
+        self.bytes.truncate(start);
+        self.open = start;
+    }
+
+    /// Empties the arena, while keeping the allocated memory.
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.segments.clear();
+        self.open = 0;
+        self.entries.clear();
+        self.scratch.clear();
+        self.depth = 0;
+    }
+}
+
+/// Returns the encoded key of a buffered map entry.
+fn entry_key<'a>(bytes: &'a [u8], segments: &[Range<usize>], entry: &Range<usize>) -> &'a [u8] {
+    // The key is a string, hence always part of the first segment of the entry.
+    let bytes = &bytes[segments[entry.start].start..];
+    let (head, len) = decode_head(bytes);
+    &bytes[..head + len as usize]
+}
+
+/// Returns the length of the head of the encoded item at the start of `bytes`, and its argument.
+fn decode_head(bytes: &[u8]) -> (usize, u64) {
+    match bytes[0] & 0x1f {
+        argument @ 0..=23 => (1, u64::from(argument)),
+        24 => (2, u64::from(bytes[1])),
+        25 => (3, u64::from(u16::from_be_bytes([bytes[1], bytes[2]]))),
+        26 => (
+            5,
+            u64::from(u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]])),
+        ),
+        _ => {
+            let mut argument = [0; 8];
+            argument.copy_from_slice(&bytes[1..9]);
+            (9, u64::from_be_bytes(argument))
+        }
+    }
+}
+
+/// Returns the length of the encoded item at the start of `bytes`.
+///
+/// The item needs to be produced by the serializer, hence valid and of definite length.
+fn item_len(bytes: &[u8]) -> usize {
+    let mut len = 0;
+    // The number of items that still need to be skipped.
+    let mut remaining = 1;
+    while remaining > 0 {
+        remaining -= 1;
+        let (head, argument) = decode_head(&bytes[len..]);
+        match dec::if_major(bytes[len]) {
+            major::BYTES | major::STRING => len += argument as usize,
+            major::ARRAY => remaining += argument,
+            major::MAP => remaining += 2 * argument,
+            major::TAG => remaining += 1,
+            // The argument of all other items is part of the head.
+            _ => {}
+        }
+        len += head;
+    }
+    len
+}
+
+/// Sorts the map entries that make up `bytes` by their keys, in place.
+///
+/// It's an insertion sort that moves each entry into place right away, as there's no memory to
+/// keep track of the entries. Entries that are in order already are appended directly. If
+/// `check_duplicates` is set, the range of the first key that is found twice is returned.
+fn sort_entries_in_place(bytes: &mut [u8], check_duplicates: bool) -> Option<Range<usize>> {
+    // The entries before `sorted` are sorted, the key of the last one is `last_key`.
+    let mut sorted = 0;
+    let mut last_key = 0..0;
+    while sorted < bytes.len() {
+        let key = sorted..sorted + item_len(&bytes[sorted..]);
+        let entry_len = key.len() + item_len(&bytes[key.end..]);
+        if sorted == 0 || bytes[last_key.clone()] < bytes[key.clone()] {
+            last_key = key;
+        } else {
+            // Find the first entry with a greater key.
+            let mut position = 0;
+            while position < sorted {
+                let other = position..position + item_len(&bytes[position..]);
+                match bytes[other.clone()].cmp(&bytes[key.clone()]) {
+                    Ordering::Greater => break,
+                    Ordering::Equal if check_duplicates => return Some(other),
+                    _ => position = other.end + item_len(&bytes[other.end..]),
+                }
+            }
+            if position == sorted {
+                last_key = key;
+            } else {
+                bytes[position..sorted + entry_len].rotate_right(entry_len);
+                last_key = last_key.start + entry_len..last_key.end + entry_len;
+            }
+        }
+        sorted += entry_len;
+    }
+    None
+}
+
+/// An integer map key that is formatted as string, without allocating.
+#[derive(Default)]
+struct IntegerKey {
+    // Integers within the range of CBOR have at most 21 characters.
+    bytes: [u8; 24],
+    len: usize,
+}
+
+impl IntegerKey {
+    fn new(key: i128) -> Self {
+        let mut formatted = Self::default();
+        // Formatting can't fail, as the buffer is large enough.
+        let _ = write!(formatted, "{}", key);
+        formatted
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+impl fmt::Write for IntegerKey {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// A writer that discards the output.
+struct Sink;
+
+impl enc::Write for Sink {
+    type Error = Infallible;
+
+    fn push(&mut self, _input: &[u8]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A writer that drops all output once the maximum output size would be exceeded.
+struct LimitedWriter<'a, W> {
+    writer: &'a mut W,
+    /// The buffer that is written to instead of `writer`, if set.
+    buffer: Option<&'a mut Vec<u8>>,
+    written: &'a mut usize,
+    limit: usize,
+    exceeded: bool,
+    /// The error if growing the buffer failed.
+    failed: Option<TryReserveError>,
+}
+
+impl<W: enc::Write> enc::Write for LimitedWriter<'_, W> {
+    type Error = W::Error;
+
+    fn push(&mut self, input: &[u8]) -> Result<(), Self::Error> {
+        if self.exceeded || self.failed.is_some() {
+            return Ok(());
+        }
+        if self.limit - *self.written < input.len() {
+            self.exceeded = true;
+            return Ok(());
+        }
+        match &mut self.buffer {
+            Some(buffer) => match buffer.try_reserve(input.len()) {
+                Ok(()) => buffer.extend_from_slice(input),
+                Err(error) => self.failed = Some(error),
+            },
+            None => self.writer.push(input)?,
+        }
+        // Only what was written is counted, as it may be read back.
+        *self.written += input.len();
+        Ok(())
+    }
+}
+
 impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
     type Ok = ();
     type Error = EncodeError<W::Error>;
@@ -71,56 +1041,47 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
 
     #[inline]
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
@@ -135,10 +1096,10 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
         if !v.is_finite() {
             Err(EncodeError::Msg(
                 "Float must be a finite number, not Infinity or NaN".into(),
+                Path::default(),
             ))
         } else {
-            v.encode(&mut self.writer)?;
-            Ok(())
+            self.write(|writer| v.encode(writer))
         }
     }
 
@@ -150,20 +1111,17 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
 
     #[inline]
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        types::Bytes(v).encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| types::Bytes(v).encode(writer))
     }
 
     #[inline]
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        types::Null.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| types::Null.encode(writer))
     }
 
     #[inline]
@@ -175,8 +1133,7 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
         // The cbor4ii Serde implementation encodes unit as an empty array, for DAG-CBOR we encode
         // it as `NULL`.
-        types::Null.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| types::Null.encode(writer))
     }
 
     #[inline]
@@ -215,8 +1172,10 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
         variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error> {
-        types::Map::bounded(1, &mut self.writer)?;
-        variant.encode(&mut self.writer)?;
+        self.write(|writer| {
+            types::Map::bounded(1, writer)?;
+            variant.encode(writer)
+        })?;
         value.serialize(self)
     }
 
@@ -227,8 +1186,9 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
 
     #[inline]
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        types::Array::bounded(len, &mut self.writer)?;
-        Ok(BoundedCollect { ser: self })
+        self.enter()?;
+        self.write(|writer| types::Array::bounded(len, writer))?;
+        Ok(BoundedCollect::new(self))
     }
 
     #[inline]
@@ -248,14 +1208,18 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        types::Map::bounded(1, &mut self.writer)?;
-        variant.encode(&mut self.writer)?;
-        types::Array::bounded(len, &mut self.writer)?;
-        Ok(BoundedCollect { ser: self })
+        self.enter()?;
+        self.write(|writer| {
+            types::Map::bounded(1, writer)?;
+            variant.encode(writer)?;
+            types::Array::bounded(len, writer)
+        })?;
+        Ok(BoundedCollect::new(self))
     }
 
     #[inline]
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.enter()?;
         Ok(CollectMap::new(self))
     }
 
@@ -263,9 +1227,9 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
     fn serialize_struct(
         self,
         _name: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        types::Map::bounded(len, &mut self.writer)?;
+        self.enter()?;
         Ok(CollectMap::new(self))
     }
 
@@ -275,11 +1239,13 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        types::Map::bounded(1, &mut self.writer)?;
-        variant.encode(&mut self.writer)?;
-        types::Map::bounded(len, &mut self.writer)?;
+        self.enter()?;
+        self.write(|writer| {
+            types::Map::bounded(1, writer)?;
+            variant.encode(writer)
+        })?;
         Ok(CollectMap::new(self))
     }
 
@@ -288,11 +1254,11 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
         if !(u64::MAX as i128 >= v && -(u64::MAX as i128 + 1) <= v) {
             return Err(EncodeError::Msg(
                 "Integer must be within [-u64::MAX-1, u64::MAX] range".into(),
+                Path::default(),
             ));
         }
 
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
@@ -300,10 +1266,10 @@ impl<'a, W: enc::Write> serde::Serializer for &'a mut Serializer<W> {
         if (u64::MAX as u128) < v {
             return Err(EncodeError::Msg(
                 "Unsigned integer must be within [0, u64::MAX] range".into(),
+                Path::default(),
             ));
         }
-        v.encode(&mut self.writer)?;
-        Ok(())
+        self.write(|writer| v.encode(writer))
     }
 
     #[inline]
@@ -318,24 +1284,47 @@ pub struct CollectSeq<'a, W> {
     /// beforehand.
     count: usize,
     ser: &'a mut Serializer<W>,
-    /// An in-memory serializer in case the number of elements is not known beforehand.
-    mem_ser: Option<Serializer<BufWriter>>,
+    /// How the header is written after the elements, in case the number of elements is not known
+    /// beforehand.
+    deferred: Option<DeferredHeader>,
+}
+
+/// How the header of a sequence of unknown length is written, after its elements.
+enum DeferredHeader {
+    /// The elements are buffered in the arena, starting at the given segment.
+    Buffered(usize),
+    /// The elements are written directly, starting after the given number of bytes of output.
+    /// The header is moved in front of them afterwards.
+    Backpatched(usize),
+    /// The output is only counted, so the header can simply be written after the elements.
+    Counted,
 }
 
 impl<'a, W: enc::Write> CollectSeq<'a, W> {
     /// If the length of the sequence is given, use it. Else buffer the sequence in order to count
-    /// the number of elements, which is then written before the elements are.
+    /// the number of elements, which is then written before the elements are. If the writer
+    /// supports it, the header is moved in front of the elements instead.
     fn new(ser: &'a mut Serializer<W>, len: Option<usize>) -> Result<Self, EncodeError<W::Error>> {
-        let mem_ser = if let Some(len) = len {
-            types::Array::bounded(len, &mut ser.writer)?;
+        ser.enter()?;
+        let deferred = if let Some(len) = len {
+            ser.write(|writer| types::Array::bounded(len, writer))?;
             None
+        } else if ser.counting {
+            Some(DeferredHeader::Counted)
+        } else if ser.tail_mut.is_some() {
+            // Without an arena, nested sequences are backpatched too.
+            Some(DeferredHeader::Backpatched(ser.written))
+        } else if ser.backpatch.is_some() && ser.arena.depth == 0 && !ser.backpatching {
+            // Nested sequences are buffered, so that every byte is moved at most once.
+            ser.backpatching = true;
+            Some(DeferredHeader::Backpatched(ser.written))
         } else {
-            Some(Serializer::new(BufWriter::new(Vec::new())))
+            Some(DeferredHeader::Buffered(ser.begin_buffered()))
         };
         Ok(Self {
             count: 0,
             ser,
-            mem_ser,
+            deferred,
         })
     }
 }
@@ -343,6 +1332,23 @@ impl<'a, W: enc::Write> CollectSeq<'a, W> {
 /// Helper for processing collections.
 pub struct BoundedCollect<'a, W> {
     ser: &'a mut Serializer<W>,
+    /// The index of the next element.
+    index: usize,
+}
+
+impl<'a, W: enc::Write> BoundedCollect<'a, W> {
+    fn new(ser: &'a mut Serializer<W>) -> Self {
+        Self { ser, index: 0 }
+    }
+
+    fn serialize<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError<W::Error>> {
+        let index = self.index;
+        self.index += 1;
+        value.serialize(&mut *self.ser).map_err(|mut error| {
+            error.prepend_path(PathSegment::Index(index));
+            error
+        })
+    }
 }
 
 impl<W: enc::Write> serde::ser::SerializeSeq for CollectSeq<'_, W> {
@@ -351,25 +1357,42 @@ impl<W: enc::Write> serde::ser::SerializeSeq for CollectSeq<'_, W> {
 
     #[inline]
     fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let index = self.count;
         self.count += 1;
-        if let Some(ser) = self.mem_ser.as_mut() {
-            value
-                .serialize(&mut *ser)
-                .map_err(|_| EncodeError::Msg("List element cannot be serialized".to_string()))
-        } else {
-            value.serialize(&mut *self.ser)
-        }
+        value.serialize(&mut *self.ser).map_err(|mut error| {
+            error.prepend_path(PathSegment::Index(index));
+            error
+        })
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
         // Data was buffered in order to be able to write out the number of elements before they
         // are serialized.
-        if let Some(ser) = self.mem_ser {
-            types::Array::bounded(self.count, &mut self.ser.writer)?;
-            self.ser.writer.push(&ser.into_inner().into_inner())?;
+        let count = self.count;
+        match self.deferred {
+            Some(DeferredHeader::Buffered(first_segment)) => {
+                self.ser.arena.close();
+                self.ser
+                    .write(|writer| types::Array::bounded(count, writer))?;
+                self.ser.arena.prepend_header(first_segment);
+                self.ser.end_buffered()?;
+            }
+            Some(DeferredHeader::Backpatched(start)) => {
+                let tail = self.ser.written - start;
+                self.ser
+                    .write(|writer| types::Array::bounded(count, writer))?;
+                self.ser.move_header(start, tail)?;
+                self.ser.backpatching = false;
+            }
+            Some(DeferredHeader::Counted) => {
+                self.ser
+                    .write(|writer| types::Array::bounded(count, writer))?;
+            }
+            None => {}
         }
 
+        self.ser.leave();
         Ok(())
     }
 }
@@ -380,11 +1403,12 @@ impl<W: enc::Write> serde::ser::SerializeTuple for BoundedCollect<'_, W> {
 
     #[inline]
     fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
-        value.serialize(&mut *self.ser)
+        self.serialize(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.ser.leave();
         Ok(())
     }
 }
@@ -395,11 +1419,12 @@ impl<W: enc::Write> serde::ser::SerializeTupleStruct for BoundedCollect<'_, W> {
 
     #[inline]
     fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
-        value.serialize(&mut *self.ser)
+        self.serialize(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.ser.leave();
         Ok(())
     }
 }
@@ -410,11 +1435,12 @@ impl<W: enc::Write> serde::ser::SerializeTupleVariant for BoundedCollect<'_, W>
 
     #[inline]
     fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
-        value.serialize(&mut *self.ser)
+        self.serialize(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.ser.leave();
         Ok(())
     }
 }
@@ -422,12 +1448,23 @@ impl<W: enc::Write> serde::ser::SerializeTupleVariant for BoundedCollect<'_, W>
 /// CBOR RFC-7049 specifies a canonical sort order, where keys are sorted by length first. This
 /// was later revised with RFC-8949, but we need to stick to the original order to stay compatible
 /// with existing data.
-/// We first serialize each map entry (the key and the value) into a buffer and then sort those
-/// buffers. Once sorted they are written to the actual output.
+/// We first serialize each map entry (the key and the value) into a buffer that is shared with all
+/// nested maps and then sort those entries. Once sorted they are written to the actual output.
 pub struct CollectMap<'a, W> {
-    buffer: BufWriter,
-    entries: Vec<Vec<u8>>,
     ser: &'a mut Serializer<W>,
+    /// The first segment of the map in the arena.
+    first_segment: usize,
+    /// The index of the first entry of the map in the arena.
+    first_entry: usize,
+    /// The first segment of the entry that is currently serialized.
+    entry_segment: usize,
+    /// Where the key of the entry that is currently serialized starts, see
+    /// [`Serializer::position`].
+    key_start: usize,
+    /// The number of entries.
+    len: usize,
+    /// Where the entries start in the output, if the map is encoded in place.
+    in_place: Option<usize>,
 }
 
 impl<'a, W> CollectMap<'a, W>
@@ -435,10 +1472,20 @@ where
     W: enc::Write,
 {
     fn new(ser: &'a mut Serializer<W>) -> Self {
+        let (first_segment, in_place) = if ser.tail_mut.is_some() {
+            (0, Some(ser.written))
+        } else {
+            (ser.begin_buffered(), None)
+        };
+        let first_entry = ser.arena.entries.len();
         Self {
-            buffer: BufWriter::new(Vec::new()),
-            entries: Vec::new(),
             ser,
+            first_segment,
+            first_entry,
+            entry_segment: first_segment,
+            key_start: 0,
+            len: 0,
+            in_place,
         }
     }
 
@@ -447,32 +1494,79 @@ where
         maybe_key: Option<&'static str>,
         value: &T,
     ) -> Result<(), EncodeError<W::Error>> {
-        // Instantiate a new serializer, so that the buffer can be re-used.
-        let mut mem_serializer = Serializer::new(&mut self.buffer);
         if let Some(key) = maybe_key {
-            key.serialize(&mut mem_serializer)
-                .map_err(|_| EncodeError::Msg("Struct key cannot be serialized.".to_string()))?;
+            self.entry_segment = self.ser.arena.close();
+            self.ser.serialize_key(key)?;
+        }
+        value.serialize(&mut *self.ser).map_err(|mut error| {
+            let key = match maybe_key {
+                Some(key) => key,
+                None => <&str>::decode(&mut SliceReader::new(self.ser.output_from(self.key_start)))
+                    .unwrap_or_default(),
+            };
+            error.prepend_path(PathSegment::Key(key.into()));
+            error
+        })?;
+
+        self.len += 1;
+        if self.in_place.is_none() {
+            let end = self.ser.arena.close();
+            self.ser.arena.entries.push(self.entry_segment..end);
         }
-        value
-            .serialize(&mut mem_serializer)
-            .map_err(|_| EncodeError::Msg("Struct value cannot be serialized.".to_string()))?;
-
-        self.entries.push(self.buffer.buffer().to_vec());
-        self.buffer.clear();
-
         Ok(())
     }
 
-    fn end(mut self) -> Result<(), EncodeError<W::Error>> {
+    fn end(self) -> Result<(), EncodeError<W::Error>> {
+        if let Some(start) = self.in_place {
+            return self.end_in_place(start);
+        }
         // This sorting step makes sure we have the expected order of the keys. Byte-wise
         // comparison over the encoded forms gives us the right order as keys in DAG-CBOR are
         // always (text) strings, hence have the same CBOR major type 3. The length of the string
         // is encoded in the prefix bits along with the major type. This means that a shorter string
         // always sorts before a longer string even with the compact length representation.
-        self.entries.sort_unstable();
-        for entry in self.entries {
-            self.ser.writer.push(&entry)?;
+        self.ser.arena.sort_entries(self.first_entry);
+        // Entries with the same key are next to each other after sorting.
+        if self.ser.options.check_duplicate_keys {
+            if let Some(key) = self.ser.arena.duplicate_key(self.first_entry) {
+                let key = <&str>::decode(&mut SliceReader::new(key)).unwrap_or_default();
+                return Err(EncodeError::DuplicateMapKey {
+                    key: key.into(),
+                    path: Path::default(),
+                });
+            }
         }
+        let len = self.len;
+        self.ser.write(|writer| types::Map::bounded(len, writer))?;
+        if self.ser.counting {
+            // Only the keys were buffered.
+            self.ser.arena.discard(self.first_segment, self.first_entry);
+        } else {
+            self.ser
+                .arena
+                .reorder_entries(self.first_segment, self.first_entry);
+        }
+        self.ser.leave();
+        self.ser.end_buffered()
+    }
+
+    /// Sorts the entries, that were written directly, within the output and then moves the header
+    /// in front of them.
+    fn end_in_place(self, start: usize) -> Result<(), EncodeError<W::Error>> {
+        let check_duplicates = self.ser.options.check_duplicate_keys;
+        let entries = self.ser.output_from(start);
+        if let Some(key) = sort_entries_in_place(entries, check_duplicates) {
+            let key = <&str>::decode(&mut SliceReader::new(&entries[key])).unwrap_or_default();
+            return Err(EncodeError::DuplicateMapKey {
+                key: key.into(),
+                path: Path::default(),
+            });
+        }
+        let len = self.len;
+        let tail = self.ser.written - start;
+        self.ser.write(|writer| types::Map::bounded(len, writer))?;
+        self.ser.move_header(start, tail)?;
+        self.ser.leave();
         Ok(())
     }
 }
@@ -486,15 +1580,47 @@ where
 
     #[inline]
     fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
-        // The key needs to be add to the buffer without any further operations. Serializing the
-        // value will then do the necessary flushing etc.
-        let mut mem_serializer = Serializer::new(&mut self.buffer);
-        key.serialize(&mut mem_serializer)
-            .map_err(|_| EncodeError::Msg("Map key cannot be serialized.".to_string()))?;
+        // The key is the start of the entry, serializing the value will then finish it.
+        self.entry_segment = self.ser.arena.close();
+        let start = self.ser.position();
+        self.key_start = start;
+        self.ser.serialize_key(key)?;
         // Map keys must be strings in DAG-CBOR.
-        if let Some(byte) = self.buffer.buffer().first() {
-            if dec::if_major(*byte) != major::STRING {
-                return Err(EncodeError::Msg("Map keys must be strings".into()));
+        if let Some(byte) = self.ser.output_from(start).first() {
+            match dec::if_major(*byte) {
+                major::STRING => {}
+                major::UNSIGNED | major::NEGATIVE if self.ser.options.stringify_integer_keys => {
+                    let key = i128::decode(&mut SliceReader::new(self.ser.output_from(start)))
+                        .map_err(|_| {
+                            EncodeError::Msg(
+                                "Map key cannot be serialized.".into(),
+                                Path::default(),
+                            )
+                        })?;
+                    let key = IntegerKey::new(key);
+                    let key = key.as_str();
+                    if self.in_place.is_some() {
+                        // The string is always longer than the integer, hence the output is
+                        // extended and then overwritten.
+                        let padding = 1 + key.len() - self.ser.output_from(start).len();
+                        self.ser.write(|writer| {
+                            enc::Write::push(writer, &[0; 16][..padding]).map_err(enc::Error::Write)
+                        })?;
+                        let output = self.ser.output_from(start);
+                        output[0] = (major::STRING << 5) | key.len() as u8;
+                        output[1..].copy_from_slice(key.as_bytes());
+                    } else {
+                        self.ser.written -= self.ser.arena.bytes.len() - start;
+                        self.ser.arena.bytes.truncate(start);
+                        self.ser.serialize_key(key)?;
+                    }
+                }
+                _ => {
+                    return Err(EncodeError::Msg(
+                        "Map keys must be strings".into(),
+                        Path::default(),
+                    ))
+                }
             }
         }
         Ok(())
@@ -507,7 +1633,6 @@ where
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        types::Map::bounded(self.entries.len(), &mut self.ser.writer)?;
         self.end()
     }
 }
@@ -615,11 +1740,15 @@ where
     }
 
     fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok, Self::Error> {
-        // The bytes of the CID is prefixed with a null byte when encoded as CBOR.
-        let prefixed = [&[0x00], value].concat();
-        // CIDs are serialized with CBOR tag 42.
-        types::Tag(CBOR_TAGS_CID.into(), types::Bytes(&prefixed[..])).encode(&mut self.0.writer)?;
-        Ok(())
+        self.0.write(|writer| {
+            // CIDs are serialized with CBOR tag 42.
+            enc::Write::push(writer, &[0xd8, CBOR_TAGS_CID]).map_err(enc::Error::Write)?;
+            // The bytes of the CID are prefixed with a null byte when encoded as CBOR. The parts
+            // are written separately, so that nothing is allocated.
+            types::Bytes::bounded(value.len() + 1, writer)?;
+            enc::Write::push(writer, &[0x00]).map_err(enc::Error::Write)?;
+            enc::Write::push(writer, value).map_err(enc::Error::Write)
+        })
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
@@ -0,0 +1,199 @@
+//! Decoding every value within DAG-CBOR data without building it.
+//!
+//! This is the common part of validating the data and walking its links.
+use alloc::string::String;
+use core::fmt;
+use core::ops::Range;
+
+use cbor4ii::core::dec;
+use ipld_core::cid::{serde::BytesToCidVisitor, Cid};
+use serde::de::{self, DeserializeSeed, Visitor};
+
+use crate::de::{Deserializer, LINK_OFFSET_IDENTIFIER};
+use crate::error::{DecodeError, Path, PathSegment};
+
+/// Receives the values of a traversal.
+pub(crate) trait Visit {
+    /// Whether the path to the links is tracked, which copies every map key.
+    const TRACK_PATH: bool;
+
+    /// Called for every value, including the elements of lists and maps, but not map keys.
+    ///
+    /// The top-level value has a depth of one.
+    fn value(&mut self, _depth: usize) {}
+
+    /// Called for every link with the path to it and the range of the input it spans.
+    ///
+    /// The path is empty, unless [`Visit::TRACK_PATH`] is set.
+    fn link(&mut self, path: &Path, cid: Cid, range: Range<usize>);
+}
+
+/// Decodes a single value and passes everything within it to `visit`.
+///
+/// Trailing data isn't checked.
+pub(crate) fn traverse<'de, R, V>(
+    de: &mut Deserializer<R>,
+    visit: &mut V,
+) -> Result<(), DecodeError<R::Error>>
+where
+    R: dec::Read<'de>,
+    V: Visit,
+{
+    let mut path = Path::default();
+    Traversal {
+        visit,
+        path: &mut path,
+        depth: 1,
+    }
+    .deserialize(de)
+}
+
+/// Visits every value and passes it on.
+struct Traversal<'a, V> {
+    visit: &'a mut V,
+    /// The path to the current value.
+    path: &'a mut Path,
+    depth: usize,
+}
+
+impl<V> Traversal<'_, V> {
+    /// Returns a traversal for the elements of a list or map.
+    fn nested(&mut self) -> Traversal<'_, V> {
+        Traversal {
+            visit: self.visit,
+            path: self.path,
+            depth: self.depth + 1,
+        }
+    }
+}
+
+impl<'de, V: Visit> DeserializeSeed<'de> for Traversal<'_, V> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.visit.value(self.depth);
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, V: Visit> Visitor<'de> for Traversal<'_, V> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any valid DAG-CBOR value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _value: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _value: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i128<E: de::Error>(self, _value: i128) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _value: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _value: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _value: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_bytes<E: de::Error>(self, _value: &[u8]) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        loop {
+            if V::TRACK_PATH {
+                self.path.push(PathSegment::Index(index));
+            }
+            let element = seq.next_element_seed(self.nested());
+            if V::TRACK_PATH {
+                self.path.pop();
+            }
+            if element?.is_none() {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        // The deserializer checks that keys are strings.
+        loop {
+            if V::TRACK_PATH {
+                match map.next_key::<String>()? {
+                    Some(key) => self.path.push(PathSegment::Key(key)),
+                    None => return Ok(()),
+                }
+            } else if map.next_key::<de::IgnoredAny>()?.is_none() {
+                return Ok(());
+            }
+            let value = map.next_value_seed(self.nested());
+            if V::TRACK_PATH {
+                self.path.pop();
+            }
+            value?;
+        }
+    }
+
+    // CIDs are the only values that are passed on as newtype struct.
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let (cid, range) =
+            deserializer.deserialize_newtype_struct(LINK_OFFSET_IDENTIFIER, LinkVisitor)?;
+        self.visit.link(self.path, cid, range);
+        Ok(())
+    }
+}
+
+/// Visits the CID of a link and the range of the input it spans.
+struct LinkVisitor;
+
+impl<'de> Visitor<'de> for LinkVisitor {
+    type Value = (Cid, Range<usize>);
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a CID")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let offset = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let cid = seq
+            .next_element_seed(CidBytes)?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let end = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        Ok((cid, offset..end))
+    }
+}
+
+/// Deserializes a CID from the content of tag 42.
+struct CidBytes;
+
+impl<'de> DeserializeSeed<'de> for CidBytes {
+    type Value = Cid;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Cid, D::Error> {
+        deserializer.deserialize_bytes(BytesToCidVisitor)
+    }
+}
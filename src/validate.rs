@@ -0,0 +1,112 @@
+//! Checking data for DAG-CBOR conformance without decoding it into values.
+//!
+//! The input is decoded with [`DeserializerOptions::strict`], but instead of building values, only
+//! some statistics about the data are collected.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_ipld_dagcbor::validate;
+//!
+//! // {"a": 1}
+//! let report = validate::validate(&[0xa1, 0x61, 0x61, 0x01]).unwrap();
+//! assert_eq!(report.values, 2);
+//!
+//! // {"b": 1, "a": 2} doesn't have its keys sorted.
+//! let error = validate::validate(&[0xa2, 0x61, 0x62, 0x01, 0x61, 0x61, 0x02]).unwrap_err();
+//! assert_eq!(error.offset, 4);
+//! ```
+use core::convert::Infallible;
+use core::fmt;
+use core::ops::Range;
+
+use cbor4ii::core::dec;
+use ipld_core::cid::Cid;
+
+use crate::de::{Deserializer, DeserializerOptions};
+use crate::error::{DecodeError, Path};
+use crate::traverse::{traverse, Visit};
+
+/// Statistics about valid DAG-CBOR data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The number of values, including the elements of lists and maps, but not map keys.
+    pub values: usize,
+    /// The number of links (CIDs).
+    pub links: usize,
+    /// The maximum nesting depth, a value that isn't a list or map has a depth of one.
+    pub max_depth: usize,
+    /// The number of bytes of the data.
+    pub len: usize,
+}
+
+/// The first violation of the DAG-CBOR rules that was found.
+#[derive(Debug)]
+pub struct ValidationError<E> {
+    /// The byte offset of the violation.
+    pub offset: usize,
+    /// The violation.
+    pub error: DecodeError<E>,
+}
+
+impl<E: fmt::Debug> fmt::Display for ValidationError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The error includes the offset already.
+        write!(f, "invalid DAG-CBOR: {}", self.error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for ValidationError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Checks whether a slice contains a single valid DAG-CBOR value.
+pub fn validate(buf: &[u8]) -> Result<ValidationReport, ValidationError<Infallible>> {
+    run(Deserializer::from_slice(buf))
+}
+
+/// Checks whether a reader contains a single valid DAG-CBOR value.
+///
+/// The whole reader is consumed, trailing data is a violation.
+#[cfg(feature = "std")]
+pub fn validate_reader<R: std::io::BufRead>(
+    reader: R,
+) -> Result<ValidationReport, ValidationError<std::io::Error>> {
+    run(Deserializer::from_reader(
+        cbor4ii::core::utils::IoReader::new(reader),
+    ))
+}
+
+fn run<'de, R: dec::Read<'de>>(
+    de: Deserializer<R>,
+) -> Result<ValidationReport, ValidationError<R::Error>> {
+    let mut de = de.with_options(DeserializerOptions::strict());
+    let mut report = ValidationReport::default();
+    let result = traverse(&mut de, &mut report).and_then(|()| de.end());
+    match result {
+        Ok(()) => {
+            report.len = de.position();
+            Ok(report)
+        }
+        Err(error) => Err(ValidationError {
+            offset: error.offset().unwrap_or_else(|| de.position()),
+            error,
+        }),
+    }
+}
+
+impl Visit for ValidationReport {
+    const TRACK_PATH: bool = false;
+
+    fn value(&mut self, depth: usize) {
+        self.values += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    fn link(&mut self, _path: &Path, _cid: Cid, _range: Range<usize>) {
+        self.links += 1;
+    }
+}
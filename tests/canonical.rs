@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use ipld_core::{cid::Cid, ipld::Ipld};
+use serde_ipld_dagcbor::{canonical::canonicalize, error::CodecError, to_vec, DecodeError};
+
+#[test]
+fn test_canonicalize() {
+    let cases: &[(&[u8], &[u8])] = &[
+        // Unsorted map keys: {"bb": 1, "a": 2, "c": 3}
+        (
+            b"\xa3\x62bb\x01\x61a\x02\x61c\x03",
+            b"\xa3\x61a\x02\x61c\x03\x62bb\x01",
+        ),
+        // Non-minimal integer, negative integer and length.
+        (b"\x9a\x00\x00\x00\x02\x19\x00\x01\x38\x00", b"\x82\x01\x20"),
+        // Indefinite length list, map, text string and byte string.
+        (
+            b"\x9f\xbf\x61a\x7f\x61b\x61c\xff\xff\x5f\x41\x01\x41\x02\xff\xff",
+            b"\x82\xa1\x61a\x62bc\x42\x01\x02",
+        ),
+        // Half and single-precision floats: [1.5, 2^-24, -2.5]
+        (
+            b"\x83\xf9\x3e\x00\xf9\x00\x01\xfa\xc0\x20\x00\x00",
+            b"\x83\xfb\x3f\xf8\x00\x00\x00\x00\x00\x00\xfb\x3e\x70\x00\x00\x00\x00\x00\x00\xfb\xc0\x04\x00\x00\x00\x00\x00\x00",
+        ),
+    ];
+    for (input, expected) in cases {
+        let (bytes, deviations) = canonicalize(input).unwrap();
+        assert_eq!(&bytes, expected, "for input {:02x?}", input);
+        assert!(!deviations.is_empty(), "for input {:02x?}", input);
+
+        let (bytes, deviations) = canonicalize(expected).unwrap();
+        assert_eq!(&bytes, expected);
+        assert!(deviations.is_empty());
+    }
+}
+
+#[test]
+fn test_canonicalize_non_minimal_cid_tag() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let expected = to_vec(&Ipld::Link(cid)).unwrap();
+    let mut input = vec![0xd9, 0x00, 0x2a];
+    input.extend_from_slice(&expected[2..]);
+
+    let (bytes, deviations) = canonicalize(&input).unwrap();
+    assert_eq!(bytes, expected);
+    assert_eq!(deviations.non_minimal_encodings, 1);
+}
+
+#[test]
+fn test_canonicalize_already_canonical() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let mut map = BTreeMap::new();
+    map.insert("link".to_string(), Ipld::Link(cid));
+    map.insert("float".to_string(), Ipld::Float(0.5));
+    map.insert("integer".to_string(), Ipld::Integer(-1_000_000));
+    map.insert("bytes".to_string(), Ipld::Bytes(vec![0x01]));
+    let input = to_vec(&Ipld::Map(map)).unwrap();
+
+    let (bytes, deviations) = canonicalize(&input).unwrap();
+    assert_eq!(bytes, input);
+    assert!(deviations.is_empty());
+}
+
+#[test]
+fn test_canonicalize_unrepresentable() {
+    let cases: &[&[u8]] = &[
+        // Integer map key.
+        b"\xa1\x01\x02",
+        // Tag other than 42.
+        b"\xc1\x1a\x51\x4b\x67\xb0",
+        // NaN as half-precision float.
+        b"\xf9\x7e\x00",
+        // Infinity as single-precision float.
+        b"\xfa\x7f\x80\x00\x00",
+        // Duplicate map keys.
+        b"\xa2\x61a\x01\x61a\x02",
+        // Undefined.
+        b"\xf7",
+        // Trailing data.
+        b"\x01\x02",
+    ];
+    for input in cases {
+        let error = canonicalize(input).unwrap_err();
+        assert!(
+            matches!(error, CodecError::Decode(_)),
+            "for input {:02x?}",
+            input
+        );
+    }
+
+    let error = canonicalize(b"\xf9\x7e\x00").unwrap_err();
+    assert!(matches!(
+        error,
+        CodecError::Decode(DecodeError::Mismatch { found: 0xf9, .. })
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_canonicalize_reader() {
+    use serde_ipld_dagcbor::canonical::canonicalize_reader;
+
+    let mut output = Vec::new();
+    let deviations = canonicalize_reader(&b"\x9f\x01\x02\xff"[..], &mut output).unwrap();
+    assert_eq!(output, b"\x82\x01\x02");
+    assert_eq!(deviations.indefinite_lengths, 1);
+}
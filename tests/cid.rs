@@ -352,3 +352,94 @@ fn test_cid_decode_from_reader() {
     let cid_decoded: Cid = from_slice(&cid_encoded).unwrap();
     assert_eq!(&cid_encoded[4..], &cid_decoded.to_bytes());
 }
+
+#[test]
+fn test_cid_validation() {
+    use serde_ipld_dagcbor::{
+        de::{Deserializer, DeserializerOptions},
+        error::InvalidCidReason,
+        DecodeError,
+    };
+
+    /// Decodes tag 42 with the given bytes into `T`, with CID validation.
+    fn decode<T: de::DeserializeOwned>(
+        bytes: &[u8],
+    ) -> Result<T, DecodeError<std::convert::Infallible>> {
+        let mut input = vec![0xd8, 0x2a, 0x58, bytes.len() as u8];
+        input.extend_from_slice(bytes);
+        let options = DeserializerOptions::new().validate_cids(true);
+        let mut deserializer = Deserializer::from_slice(&input).with_options(options);
+        de::Deserialize::deserialize(&mut deserializer)
+    }
+
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let cid_v0 = Cid::from_str("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+    for cid in [cid, cid_v0] {
+        let bytes = [&[0x00], &cid.to_bytes()[..]].concat();
+        assert_eq!(decode::<Cid>(&bytes).unwrap(), cid);
+        assert_eq!(decode::<Ipld>(&bytes).unwrap(), Ipld::Link(cid));
+    }
+
+    // The offsets are relative to the start of the CID bytes, the input has a 4 byte prefix.
+    let valid = [&[0x00], &cid.to_bytes()[..]].concat();
+    let cases: Vec<(Vec<u8>, InvalidCidReason, usize)> = vec![
+        (
+            valid[1..].to_vec(),
+            InvalidCidReason::MissingMultibasePrefix,
+            0,
+        ),
+        (
+            valid[..valid.len() - 1].to_vec(),
+            InvalidCidReason::Truncated,
+            valid.len() - 1,
+        ),
+        (
+            [&valid[..], &[0x00]].concat(),
+            InvalidCidReason::TrailingBytes,
+            valid.len(),
+        ),
+        (
+            vec![0x00, 0x02, 0x55, 0x12, 0x01, 0x00],
+            InvalidCidReason::UnsupportedVersion(2),
+            1,
+        ),
+        (
+            vec![0x00, 0x12, 0x20, 0x00],
+            InvalidCidReason::InvalidCidV0,
+            1,
+        ),
+        // The codec 0x55 encoded with a redundant continuation byte.
+        (
+            vec![0x00, 0x01, 0xd5, 0x00, 0x12, 0x01, 0x00],
+            InvalidCidReason::InvalidVarint,
+            2,
+        ),
+        (vec![0x00, 0x01, 0x55], InvalidCidReason::Truncated, 3),
+        (
+            [&[0x00, 0x01][..], &[0xff; 10]].concat(),
+            InvalidCidReason::InvalidVarint,
+            2,
+        ),
+    ];
+    for (bytes, expected, offset) in cases {
+        let err = decode::<Ipld>(&bytes).unwrap_err();
+        match err {
+            DecodeError::InvalidCid { reason, .. } => {
+                assert_eq!(reason, expected, "for {:02x?}", bytes)
+            }
+            _ => panic!("unexpected error {:?} for {:02x?}", err, bytes),
+        }
+        assert_eq!(err.offset(), Some(4 + offset), "for {:02x?}", bytes);
+    }
+
+    let err = decode::<Cid>(&[0x00, 0x02, 0x55, 0x12, 0x01, 0x00]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid CID, version 2 is not supported at byte 5 (02 55 12 01 00)"
+    );
+
+    // Without validation, only the prefix is checked.
+    let input = [0xd8, 0x2a, 0x44, 0x00, 0x01, 0x55, 0x12];
+    let err = from_slice::<Cid>(&input).unwrap_err();
+    assert!(matches!(err, DecodeError::Msg(..)));
+}
@@ -1,4 +1,4 @@
-#![cfg(all(feature = "std", not(feature = "no-cid-as-bytes")))]
+#![cfg(feature = "std")]
 
 use core::{convert::TryFrom, iter};
 
@@ -8,7 +8,10 @@ use ipld_core::{
     ipld,
     ipld::Ipld,
 };
-use serde_ipld_dagcbor::codec::DagCborCodec;
+use serde_ipld_dagcbor::{
+    codec::DagCborCodec,
+    error::{CodecErrorKind, DecodeErrorKind},
+};
 
 #[test]
 fn test_codec_encode() {
@@ -64,3 +67,44 @@ fn test_codec_generic() {
     let encoded = encode_generic::<DagCborCodec, _>(data);
     assert_eq!(encoded, expected);
 }
+
+#[test]
+fn test_codec_error() {
+    let error = <DagCborCodec as Codec<Ipld>>::decode_from_slice(b"\x9f\x01\xff").unwrap_err();
+    assert_eq!(
+        error.kind(),
+        CodecErrorKind::Decode(DecodeErrorKind::IndefiniteSize)
+    );
+    assert_eq!(
+        error.to_string(),
+        "failed to decode DAG-CBOR: indefinite-length item at byte 0 (9f 01 ff); DAG-CBOR only \
+         allows definite lengths"
+    );
+}
+
+#[test]
+fn test_codec_links_errors() {
+    let cid = Cid::try_from("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let mut encoded = DagCborCodec::encode_to_vec(&Ipld::Link(cid)).unwrap();
+    encoded.push(0x01);
+    let error = DagCborCodec::links(&encoded).err().unwrap();
+    assert_eq!(
+        error.kind(),
+        CodecErrorKind::Decode(DecodeErrorKind::TrailingData)
+    );
+
+    // A list of one element that contains a string of indefinite length.
+    let error = DagCborCodec::links(b"\x81\x7f\x61a\xff").err().unwrap();
+    assert_eq!(
+        error.kind(),
+        CodecErrorKind::Decode(DecodeErrorKind::IndefiniteSize)
+    );
+
+    // Map keys must be strings, like when decoding.
+    let link = DagCborCodec::encode_to_vec(&Ipld::Link(cid)).unwrap();
+    let encoded = [&b"\xa1\x01"[..], &link].concat();
+    let decode_error = <DagCborCodec as Codec<Ipld>>::decode_from_slice(&encoded).unwrap_err();
+    let error = DagCborCodec::links(&encoded).err().unwrap();
+    assert_eq!(error.kind(), decode_error.kind());
+    assert_eq!(error.to_string(), decode_error.to_string());
+}
@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
+use std::str::FromStr;
 
-use ipld_core::ipld::Ipld;
+use ipld_core::{cid::Cid, ipld::Ipld};
 use serde::{Deserialize, Serialize};
 use serde_ipld_dagcbor::{de, to_vec, DecodeError};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
@@ -73,7 +74,10 @@ fn test_bool() {
 #[test]
 fn test_trailing_bytes() {
     let ipld: Result<Ipld, _> = de::from_slice(b"\xf4trailing");
-    assert!(matches!(ipld.unwrap_err(), DecodeError::TrailingData));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::TrailingData { .. }
+    ));
 }
 
 #[test]
@@ -130,7 +134,7 @@ fn test_non_string_map_keys_rejected() {
         // The second byte is the actual type that is reported in error messages.
         let found = &bytes[1];
         assert!(
-            matches!(&err, DecodeError::Mismatch { name: "map key", found: f } if f == found),
+            matches!(&err, DecodeError::Mismatch { name: "map key", found: f, .. } if f == found),
             "{}: expected map key mismatch with found={:#04x}, got {:?}",
             what,
             found,
@@ -148,33 +152,48 @@ fn test_indefinite_object_error() {
         "b".to_string(),
         Ipld::List(vec![Ipld::Integer(2), Ipld::Integer(3)]),
     );
-    assert!(matches!(ipld.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
 
 #[test]
 fn test_indefinite_list_error() {
     let ipld: Result<Ipld, _> = de::from_slice(b"\x9f\x01\x02\x03\xff");
-    assert!(matches!(ipld.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
 
 #[test]
 fn test_indefinite_string_error() {
     let ipld: Result<Ipld, _> =
         de::from_slice(b"\x7f\x65Mary \x64Had \x62a \x67Little \x60\x64Lamb\xff");
-    assert!(matches!(ipld.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
 
 #[test]
 fn test_indefinite_byte_string_error() {
     let ipld: Result<Ipld, _> = de::from_slice(b"\x5f\x42\x01\x23\x42\x45\x67\xff");
-    assert!(matches!(ipld.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
 
 #[test]
 fn test_multiple_indefinite_strings_error() {
     let input = b"\x82\x7f\x65Mary \x64Had \x62a \x67Little \x60\x64Lamb\xff\x5f\x42\x01\x23\x42\x45\x67\xff";
     let ipld: Result<Ipld, _> = de::from_slice(input);
-    assert!(matches!(ipld.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
 
 #[test]
@@ -192,7 +211,8 @@ fn test_rejected_tag() {
         ipld.unwrap_err(),
         DecodeError::Mismatch {
             name: "CBOR tag head",
-            found: 0xd9
+            found: 0xd9,
+            ..
         }
     ));
 
@@ -202,11 +222,219 @@ fn test_rejected_tag() {
         ipld.unwrap_err(),
         DecodeError::Mismatch {
             name: "CBOR tag",
-            found: 0x28
+            found: 0x28,
+            ..
         }
     ));
 }
 
+#[test]
+fn test_strict_non_minimal_integers() {
+    let cases: &[&[u8]] = &[
+        &[0x18, 0x05],
+        &[0x19, 0x00, 0xff],
+        &[0x1a, 0x00, 0x00, 0xff, 0xff],
+        &[0x1b, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff],
+        // Negative integer -1.
+        &[0x38, 0x00],
+    ];
+    for input in cases {
+        let value: Result<i64, _> = de::from_slice(input);
+        assert!(value.is_ok(), "for input {:02x?}", input);
+        let value: Result<i64, _> = de::from_slice_strict(input);
+        assert!(
+            matches!(value.unwrap_err(), DecodeError::NonMinimalEncoding { .. }),
+            "for input {:02x?}",
+            input
+        );
+        let ipld: Result<Ipld, _> = de::from_slice_strict(input);
+        assert!(
+            matches!(ipld.unwrap_err(), DecodeError::NonMinimalEncoding { .. }),
+            "for input {:02x?}",
+            input
+        );
+    }
+
+    // The smallest values of each encoding size are minimal.
+    let value: u64 = de::from_slice_strict(&[0x18, 0x18]).unwrap();
+    assert_eq!(value, 24);
+    let value: u64 = de::from_slice_strict(&[0x19, 0x01, 0x00]).unwrap();
+    assert_eq!(value, 256);
+    let value: u64 = de::from_slice_strict(&[0x1a, 0x00, 0x01, 0x00, 0x00]).unwrap();
+    assert_eq!(value, 65536);
+    let value: i64 = de::from_slice_strict(&[0x38, 0x18]).unwrap();
+    assert_eq!(value, -25);
+}
+
+#[test]
+fn test_strict_non_minimal_lengths() {
+    let cases: &[&[u8]] = &[
+        // String "abc".
+        b"\x78\x03abc",
+        // Bytes [0x00].
+        &[0x58, 0x01, 0x00],
+        // List [1].
+        &[0x98, 0x01, 0x01],
+        // Map {"a": 1}.
+        &[0xb8, 0x01, 0x61, 0x61, 0x01],
+    ];
+    for input in cases {
+        let ipld: Result<Ipld, _> = de::from_slice(input);
+        assert!(ipld.is_ok(), "for input {:02x?}", input);
+        let ipld: Result<Ipld, _> = de::from_slice_strict(input);
+        assert!(
+            matches!(ipld.unwrap_err(), DecodeError::NonMinimalEncoding { .. }),
+            "for input {:02x?}",
+            input
+        );
+    }
+
+    // List [1, CID] with tag 42 encoded in two bytes, which isn't accepted by default.
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let link = to_vec(&Ipld::Link(cid)).unwrap();
+    let input = [&b"\x82\x01\xd9\x00\x2a"[..], &link[2..]].concat();
+    let ipld: Result<Ipld, _> = de::from_slice(&input);
+    assert!(matches!(ipld.unwrap_err(), DecodeError::Mismatch { .. }));
+    let options = de::DeserializerOptions::new().require_minimal_encoding(true);
+    for result in [
+        de::from_slice_strict::<Ipld>(&input),
+        de::from_slice_with::<Ipld>(&input, options),
+    ] {
+        let error = result.unwrap_err();
+        assert!(matches!(error, DecodeError::NonMinimalEncoding { .. }));
+        assert_eq!(error.offset(), Some(2));
+    }
+}
+
+#[test]
+fn test_strict_indefinite_strings() {
+    let cases: &[(&[u8], usize)] = &[
+        // String "ab".
+        (b"\x7f\x61a\x61b\xff", 0),
+        // Bytes [0x01, 0x02].
+        (b"\x5f\x41\x01\x41\x02\xff", 0),
+        // Map {"a": 1}, with the key as string of indefinite length.
+        (b"\xa1\x7f\x61a\xff\x01", 1),
+        // List ["a"].
+        (b"\x81\x7f\x61a\xff", 1),
+    ];
+    for (input, offset) in cases {
+        let ipld: Result<Ipld, _> = de::from_slice_strict(input);
+        let error = ipld.unwrap_err();
+        assert!(
+            matches!(error, DecodeError::IndefiniteSize { .. }),
+            "for input {:02x?}",
+            input
+        );
+        assert_eq!(error.offset(), Some(*offset), "for input {:02x?}", input);
+    }
+
+    let value: Result<String, _> = de::from_slice_strict(b"\x7f\x61a\xff");
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
+    let value: Result<BTreeMap<String, u8>, _> = de::from_slice_strict(b"\xa1\x7f\x61a\xff\x01");
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
+}
+
+#[test]
+fn test_strict_map_key_order() {
+    // {"b": 1, "aa": 2} is sorted, as shorter keys come first.
+    let sorted = [0xa2, 0x61, 0x62, 0x01, 0x62, 0x61, 0x61, 0x02];
+    let ipld: Ipld = de::from_slice_strict(&sorted).unwrap();
+    assert_eq!(ipld, de::from_slice::<Ipld>(&sorted).unwrap());
+
+    // {"aa": 1, "b": 2}
+    let unsorted_length = [0xa2, 0x62, 0x61, 0x61, 0x01, 0x61, 0x62, 0x02];
+    // {"b": 1, "a": 2}
+    let unsorted_bytes = [0xa2, 0x61, 0x62, 0x01, 0x61, 0x61, 0x02];
+    for input in [&unsorted_length[..], &unsorted_bytes[..]] {
+        let ipld: Result<Ipld, _> = de::from_slice(input);
+        assert!(ipld.is_ok());
+        let ipld: Result<Ipld, _> = de::from_slice_strict(input);
+        assert!(matches!(
+            ipld.unwrap_err(),
+            DecodeError::UnsortedMapKeys { .. }
+        ));
+        let map: Result<BTreeMap<String, u8>, _> = de::from_slice_strict(input);
+        assert!(matches!(
+            map.unwrap_err(),
+            DecodeError::UnsortedMapKeys { .. }
+        ));
+    }
+}
+
+#[test]
+fn test_strict_duplicate_map_key() {
+    // {"a": 1, "a": 2}
+    let input = [0xa2, 0x61, 0x61, 0x01, 0x61, 0x61, 0x02];
+    let map: BTreeMap<String, u8> = de::from_slice(&input).unwrap();
+    assert_eq!(map["a"], 2);
+    let map: Result<BTreeMap<String, u8>, _> = de::from_slice_strict(&input);
+    assert!(matches!(
+        map.unwrap_err(),
+        DecodeError::DuplicateMapKey { .. }
+    ));
+    let ipld: Result<Ipld, _> = de::from_slice_strict(&input);
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::DuplicateMapKey { .. }
+    ));
+
+    // Nested within a field that is ignored.
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Single {
+        a: u8,
+    }
+    let input = [
+        0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0xa2, 0x61, 0x61, 0x01, 0x61, 0x61, 0x02,
+    ];
+    let value: Single = de::from_slice(&input).unwrap();
+    assert_eq!(value, Single { a: 1 });
+    let value: Result<Single, _> = de::from_slice_strict(&input);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::DuplicateMapKey { .. }
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_strict_map_keys_from_reader() {
+    let options = de::DeserializerOptions::strict();
+    let sorted: &[u8] = &[0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x02];
+    let map: BTreeMap<String, u8> = de::from_reader_with(sorted, options).unwrap();
+    assert_eq!(map.len(), 2);
+    let unsorted: &[u8] = &[0xa2, 0x61, 0x62, 0x01, 0x61, 0x61, 0x02];
+    let map: Result<BTreeMap<String, u8>, _> = de::from_reader_with(unsorted, options);
+    assert!(matches!(
+        map.unwrap_err(),
+        DecodeError::UnsortedMapKeys { .. }
+    ));
+}
+
+#[test]
+fn test_strict_non_minimal_ignored_field() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Single {
+        a: u8,
+    }
+
+    // {"a": 1, "b": 5} where 5 is not minimally encoded.
+    let input = [0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x18, 0x05];
+    let value: Single = de::from_slice(&input).unwrap();
+    assert_eq!(value, Single { a: 1 });
+    let value: Result<Single, _> = de::from_slice_strict(&input);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::NonMinimalEncoding { .. }
+    ));
+}
+
 #[test]
 fn test_crazy_list() {
     let slice = b"\x86\x1b\x00\x00\x00\x1c\xbe\x99\x1d\xc7\x3b\x00\x7a\xcf\x51\xdc\x51\x70\xdb\x3a\x1b\x3a\x06\xdd\xf5\xf6\xfb\x41\x76\x5e\xb1\xf8\x00\x00\x00";
@@ -328,7 +556,10 @@ fn test_unit() {
 fn test_variable_length_map_error() {
     let slice = b"\xbf\x67\x6d\x65\x73\x73\x61\x67\x65\x64\x70\x6f\x6e\x67\xff";
     let ipld: Result<Ipld, _> = de::from_slice(slice);
-    assert!(matches!(ipld.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
 
 #[test]
@@ -432,6 +663,527 @@ fn attempt_stack_overflow() {
     serde_ipld_dagcbor::from_slice::<Ipld>(&input).expect_err("recursion limit");
 }
 
+/// Returns `depth` nested arrays, with an integer as the innermost value.
+fn nested_arrays(depth: usize) -> Vec<u8> {
+    let mut input = vec![0x81; depth];
+    input.push(0x00);
+    input
+}
+
+#[test]
+fn test_default_max_depth() {
+    de::from_slice::<Ipld>(&nested_arrays(256)).unwrap();
+    let ipld: Result<Ipld, _> = de::from_slice(&nested_arrays(257));
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::DepthOverflow { limit: 256, .. }
+    ));
+}
+
+#[test]
+fn test_configured_max_depth() {
+    let options = de::DeserializerOptions::new().max_depth(10);
+    de::from_slice_with::<Ipld>(&nested_arrays(10), options).unwrap();
+    let ipld: Result<Ipld, _> = de::from_slice_with(&nested_arrays(11), options);
+    assert!(matches!(
+        ipld.unwrap_err(),
+        DecodeError::DepthOverflow { limit: 10, .. }
+    ));
+
+    // Decoding is recursive, deep data needs a large enough stack.
+    std::thread::Builder::new()
+        .stack_size(16 * 1024 * 1024)
+        .spawn(|| {
+            let options = de::DeserializerOptions::new().max_depth(1000);
+            de::from_slice_with::<Ipld>(&nested_arrays(500), options).unwrap();
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_max_depth_ignored_field() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Single {
+        a: u8,
+    }
+
+    // {"a": 1, "b": [[[[[0]]]]]}, the outer map is one level as well.
+    let mut input = vec![0xa2, 0x61, 0x61, 0x01, 0x61, 0x62];
+    input.extend_from_slice(&nested_arrays(5));
+    let value: Single = de::from_slice(&input).unwrap();
+    assert_eq!(value, Single { a: 1 });
+
+    for options in [
+        de::DeserializerOptions::new(),
+        de::DeserializerOptions::strict(),
+    ] {
+        let value: Result<Single, _> = de::from_slice_with(&input, options.max_depth(5));
+        assert!(matches!(
+            value.unwrap_err(),
+            DecodeError::DepthOverflow { limit: 5, .. }
+        ));
+    }
+}
+
+#[test]
+fn test_max_depth_option_and_enum() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Choice {
+        Number(u8),
+        Unit,
+    }
+
+    // [1] decoded into `Some` has a depth of two.
+    let options = de::DeserializerOptions::new().max_depth(2);
+    let value: Option<Vec<u8>> = de::from_slice_with(b"\x81\x01", options).unwrap();
+    assert_eq!(value, Some(vec![1]));
+    let value: Result<Option<Vec<u8>>, _> = de::from_slice_with(b"\x81\x01", options.max_depth(1));
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::DepthOverflow { limit: 1, .. }
+    ));
+
+    // An enum counts as one level, also if it's encoded as string.
+    let input = b"\xa1\x66Number\x01";
+    let value: Choice = de::from_slice_with(input, options.max_depth(1)).unwrap();
+    assert_eq!(value, Choice::Number(1));
+    let value: Choice = de::from_slice_with(b"\x64Unit", options.max_depth(1)).unwrap();
+    assert_eq!(value, Choice::Unit);
+    let value: String = de::from_slice_with(b"\x64Unit", options.max_depth(0)).unwrap();
+    assert_eq!(value, "Unit");
+    let value: Result<Choice, _> = de::from_slice_with(b"\x64Unit", options.max_depth(0));
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::DepthOverflow { limit: 0, .. }
+    ));
+}
+
+#[test]
+fn test_max_string_len() {
+    let options = de::DeserializerOptions::new().max_string_len(4);
+    let value: String = de::from_slice_with(b"\x64abcd", options).unwrap();
+    assert_eq!(value, "abcd");
+    let value: Result<String, _> = de::from_slice_with(b"\x65abcde", options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::StringTooLong {
+            len: 5,
+            limit: 4,
+            ..
+        }
+    ));
+    // Strings of indefinite length are limited by their total length.
+    let options = options.allow_indefinite_lengths(true);
+    let value: Result<String, _> = de::from_slice_with(b"\x7f\x63abc\x62de\xff", options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::StringTooLong { limit: 4, .. }
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_limits_checked_before_reading() {
+    // A byte string that claims to be 4 GiB long, but has no data.
+    let input: &[u8] = &[0x5a, 0xff, 0xff, 0xff, 0xff];
+    let options = de::DeserializerOptions::new().max_string_len(1024 * 1024);
+    let value: Result<serde_bytes::ByteBuf, _> = de::from_reader_with(input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::StringTooLong { .. }
+    ));
+    let options = de::DeserializerOptions::new().max_allocated_bytes(1024 * 1024);
+    let value: Result<serde_bytes::ByteBuf, _> = de::from_reader_with(input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::AllocationLimitExceeded { .. }
+    ));
+
+    // An array that claims to have 4 billion elements.
+    let input: &[u8] = &[0x9a, 0xff, 0xff, 0xff, 0xff, 0x00];
+    let options = de::DeserializerOptions::new().max_collection_len(1000);
+    let value: Result<Ipld, _> = de::from_reader_with(input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::CollectionTooLong { limit: 1000, .. }
+    ));
+    let options = de::DeserializerOptions::new().max_total_items(1000);
+    let value: Result<Ipld, _> = de::from_reader_with(input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::TooManyItems { limit: 1000, .. }
+    ));
+}
+
+#[test]
+fn test_max_collection_len() {
+    let options = de::DeserializerOptions::new().max_collection_len(2);
+    // [1, 2]
+    let value: Vec<u8> = de::from_slice_with(&[0x82, 0x01, 0x02], options).unwrap();
+    assert_eq!(value, [1, 2]);
+    // [1, 2, 3]
+    let value: Result<Vec<u8>, _> = de::from_slice_with(&[0x83, 0x01, 0x02, 0x03], options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::CollectionTooLong {
+            len: 3,
+            limit: 2,
+            ..
+        }
+    ));
+    // {"a": 1, "b": 2, "c": 3}
+    let input = [0xa3, 0x61, 0x61, 0x01, 0x61, 0x62, 0x02, 0x61, 0x63, 0x03];
+    let value: Result<Ipld, _> = de::from_slice_with(&input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::CollectionTooLong { len: 3, .. }
+    ));
+}
+
+#[test]
+fn test_max_total_items() {
+    // [[1, 2], {"a": 3}] has 2 + 2 + 2 items.
+    let input = [0x82, 0x82, 0x01, 0x02, 0xa1, 0x61, 0x61, 0x03];
+    let options = de::DeserializerOptions::new().max_total_items(6);
+    de::from_slice_with::<Ipld>(&input, options).unwrap();
+    let options = de::DeserializerOptions::new().max_total_items(5);
+    let value: Result<Ipld, _> = de::from_slice_with(&input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::TooManyItems { limit: 5, .. }
+    ));
+
+    // Items of ignored fields count as well.
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Single {
+        a: u8,
+    }
+    // {"a": 1, "b": [1, 2, 3]}
+    let input = [0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x83, 0x01, 0x02, 0x03];
+    let value: Result<Single, _> = de::from_slice_with(&input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::TooManyItems { limit: 5, .. }
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_max_allocated_bytes() {
+    // ["abcd", "efgh"]
+    let input: &[u8] = b"\x82\x64abcd\x64efgh";
+    let options = de::DeserializerOptions::new().max_allocated_bytes(8);
+    let value: Vec<String> = de::from_reader_with(input, options).unwrap();
+    assert_eq!(value, ["abcd", "efgh"]);
+    let options = de::DeserializerOptions::new().max_allocated_bytes(7);
+    let value: Result<Vec<String>, _> = de::from_reader_with(input, options);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::AllocationLimitExceeded { limit: 7, .. }
+    ));
+
+    // Borrowed data doesn't count.
+    let value: Vec<&str> = de::from_slice_with(input, options).unwrap();
+    assert_eq!(value, ["abcd", "efgh"]);
+}
+
+#[test]
+fn test_error_offsets() {
+    // A string where an integer is expected: [1, "a"]
+    let error = de::from_slice::<Vec<u8>>(b"\x82\x01\x61a").unwrap_err();
+    assert!(matches!(error, DecodeError::Mismatch { .. }));
+    assert_eq!(error.offset(), Some(2));
+    assert_eq!(error.location().window(), b"\x61a");
+
+    // Errors about a head point to its start: [1, 5] with 5 encoded in two bytes.
+    let error = de::from_slice_strict::<Vec<u8>>(b"\x82\x01\x18\x05").unwrap_err();
+    assert!(matches!(error, DecodeError::NonMinimalEncoding { .. }));
+    assert_eq!(error.offset(), Some(2));
+    assert_eq!(error.location().window(), b"\x18\x05");
+
+    // Unsorted map keys point to the key that is out of order: {"b": 1, "a": 2}
+    let error =
+        de::from_slice_strict::<BTreeMap<String, u8>>(b"\xa2\x61b\x01\x61a\x02").unwrap_err();
+    assert!(matches!(error, DecodeError::UnsortedMapKeys { .. }));
+    assert_eq!(error.offset(), Some(4));
+
+    // Integers out of range point to their head: [1, 256]
+    let error = de::from_slice::<(u8, u8)>(b"\x82\x01\x19\x01\x00").unwrap_err();
+    assert!(matches!(error, DecodeError::CastOverflow { .. }));
+    assert_eq!(error.offset(), Some(2));
+    assert_eq!(error.location().window(), b"\x19\x01\x00");
+    let error = de::from_slice::<(u8, u128)>(b"\x82\x01\x20").unwrap_err();
+    assert!(matches!(error, DecodeError::CastOverflow { .. }));
+    assert_eq!(error.offset(), Some(2));
+
+    let error = de::from_slice::<u8>(b"\x01\x02").unwrap_err();
+    assert!(matches!(error, DecodeError::TrailingData { .. }));
+    assert_eq!(error.offset(), Some(1));
+
+    let display = de::from_slice::<u8>(b"\x82\x01\x02")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        display,
+        "unexpected array (82) while decoding u8 at byte 0 (82 01 02)"
+    );
+}
+
+#[test]
+fn test_error_offsets_deserializer() {
+    // Errors have the same offsets when the deserializer is used directly.
+    let input = b"\x82\x01\x18";
+    let mut deserializer = de::Deserializer::from_slice(input);
+    let error = Vec::<u8>::deserialize(&mut deserializer).unwrap_err();
+    assert!(matches!(error, DecodeError::Eof { .. }));
+    assert_eq!(error.offset(), Some(3));
+    assert_eq!(
+        error.offset(),
+        de::from_slice::<Vec<u8>>(input).unwrap_err().offset()
+    );
+
+    // Errors of the visitor: [1, "a"]
+    let mut deserializer = de::Deserializer::from_slice(b"\x82\x01\x61a");
+    let error = Vec::<u8>::deserialize(&mut deserializer).unwrap_err();
+    assert!(matches!(error, DecodeError::Mismatch { .. }));
+    assert_eq!(error.offset(), Some(2));
+}
+
+#[test]
+fn test_error_display() {
+    let cases: &[(&[u8], &str)] = &[
+        (
+            b"\x82\x01\xd8\x28\x42\x00\x01",
+            "unsupported CBOR tag 40 at byte 2 (d8 28 42 00 01); DAG-CBOR only allows tag 42, \
+             encoded as d8 2a",
+        ),
+        (
+            b"\x9f\x01\xff",
+            "indefinite-length item at byte 0 (9f 01 ff); DAG-CBOR only allows definite lengths",
+        ),
+        (b"\x01\x02", "trailing data after the value at byte 1 (02)"),
+        (
+            b"\x63ab",
+            "unexpected end of input while decoding str, 1 more byte was expected at byte 3",
+        ),
+    ];
+    for (input, expected) in cases {
+        let error = de::from_slice::<Ipld>(input).unwrap_err();
+        assert_eq!(error.to_string(), *expected);
+    }
+
+    let error = de::from_slice::<Ipld>(b"\x9f\x01\xff").unwrap_err();
+    assert_eq!(
+        error.kind(),
+        serde_ipld_dagcbor::error::DecodeErrorKind::IndefiniteSize
+    );
+    assert_eq!(error.kind().as_str(), "indefinite_size");
+}
+
+#[test]
+fn test_lenient_indefinite_lengths() {
+    // {_ "a": [_ 1, 2], "b": (_ "c", "d")}
+    let input = b"\xbf\x61a\x9f\x01\x02\xff\x61b\x7f\x61c\x61d\xff\xff";
+    assert!(matches!(
+        de::from_slice::<Ipld>(input).unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
+
+    let (ipld, deviations) = de::from_slice_lenient::<Ipld>(input).unwrap();
+    assert_eq!(deviations.indefinite_lengths, 3);
+    assert_eq!(to_vec(&ipld).unwrap(), b"\xa2\x61a\x82\x01\x02\x61b\x62cd");
+
+    // Typed values work as well.
+    let (value, _) = de::from_slice_lenient::<(u8, u8)>(b"\x9f\x01\x02\xff").unwrap();
+    assert_eq!(value, (1, 2));
+    let error = de::from_slice_lenient::<(u8, u8)>(b"\x9f\x01\x02\x03\xff").unwrap_err();
+    assert!(matches!(
+        error,
+        DecodeError::LengthMismatch {
+            expect: 2,
+            value: 3,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_lenient_non_minimal_tag() {
+    let cid = b"\x58\x25\x00\x01\x55\x12\x20\x2c\x26\xb4\x6b\x68\xff\xc6\x8f\xf9\x9b\x45\x3c\x1d\x30\x41\x34\x13\x42\x2d\x70\x64\x83\xbf\xa0\xf9\x8a\x5e\x88\x62\x66\xe7\xae";
+    let mut input = b"\xd9\x00\x2a".to_vec();
+    input.extend_from_slice(cid);
+    assert!(matches!(
+        de::from_slice::<Ipld>(&input).unwrap_err(),
+        DecodeError::Mismatch {
+            name: "CBOR tag head",
+            ..
+        }
+    ));
+
+    let (ipld, deviations) = de::from_slice_lenient::<Ipld>(&input).unwrap();
+    assert!(matches!(ipld, Ipld::Link(_)));
+    assert_eq!(deviations.non_minimal_encodings, 1);
+    let mut expected = b"\xd8\x2a".to_vec();
+    expected.extend_from_slice(cid);
+    assert_eq!(to_vec(&ipld).unwrap(), expected);
+
+    // Other tags are still rejected.
+    let mut input = b"\xd9\x01\x2a".to_vec();
+    input.extend_from_slice(cid);
+    assert!(de::from_slice_lenient::<Ipld>(&input).is_err());
+}
+
+#[test]
+fn test_lenient_deviations() {
+    // {"b": 1.5 as f32, "a": 24 as two bytes}
+    let input = b"\xa2\x61b\xfa\x3f\xc0\x00\x00\x61a\x19\x00\x18";
+    let (ipld, deviations) = de::from_slice_lenient::<Ipld>(input).unwrap();
+    assert_eq!(
+        deviations,
+        de::Deviations {
+            indefinite_lengths: 0,
+            non_minimal_encodings: 1,
+            f16_floats: 0,
+            f32_floats: 1,
+            unsorted_map_keys: 1,
+            duplicate_map_keys: 0,
+        }
+    );
+    assert_eq!(
+        to_vec(&ipld).unwrap(),
+        b"\xa2\x61a\x18\x18\x61b\xfb\x3f\xf8\x00\x00\x00\x00\x00\x00"
+    );
+
+    // Ignored values are checked as well.
+    #[derive(Debug, Deserialize)]
+    struct Single {
+        #[allow(dead_code)]
+        a: u8,
+    }
+    let (_, deviations) = de::from_slice_lenient::<Single>(input).unwrap();
+    assert_eq!(deviations.f32_floats, 1);
+    assert_eq!(deviations.unsorted_map_keys, 1);
+
+    let (_, deviations) = de::from_slice_lenient::<Ipld>(b"\xa1\x61a\x01").unwrap();
+    assert!(deviations.is_empty());
+}
+
+#[test]
+fn test_lenient_typed_floats() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Sample {
+        x: f64,
+        y: f32,
+    }
+
+    // {"x": 1.5 as f32, "y": 0.5 as f16}
+    let input = b"\xa2\x61x\xfa\x3f\xc0\x00\x00\x61y\xf9\x38\x00";
+    let (value, deviations) = de::from_slice_lenient::<Sample>(input).unwrap();
+    assert_eq!(value, Sample { x: 1.5, y: 0.5 });
+    assert_eq!(deviations.f32_floats, 1);
+    assert_eq!(deviations.f16_floats, 1);
+
+    // {"x": 0.5 as f16, "y": 1.5 as f32}
+    let input = b"\xa2\x61x\xf9\x38\x00\x61y\xfa\x3f\xc0\x00\x00";
+    let (value, deviations) = de::from_slice_lenient::<Sample>(input).unwrap();
+    assert_eq!(value, Sample { x: 0.5, y: 1.5 });
+    assert_eq!(deviations.f32_floats, 1);
+    assert_eq!(deviations.f16_floats, 1);
+
+    // Half-precision floats are rejected by default.
+    let value: Result<Sample, _> = de::from_slice(input);
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::Mismatch { name: "f64", .. }
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_lenient_from_reader() {
+    let (value, deviations) =
+        de::from_reader_lenient::<Vec<String>, _>(&b"\x9f\x7f\x61a\x61b\xff\xff"[..]).unwrap();
+    assert_eq!(value, ["ab"]);
+    assert_eq!(deviations.indefinite_lengths, 2);
+}
+
+#[test]
+fn test_error_path() {
+    #[derive(Debug, Deserialize)]
+    struct Header {
+        #[allow(dead_code)]
+        parents: Vec<u8>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Entry {
+        #[allow(dead_code)]
+        header: Header,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Root {
+        #[allow(dead_code)]
+        entries: Vec<Entry>,
+    }
+
+    let entry = |parent: Ipld| {
+        Ipld::Map(BTreeMap::from([(
+            "header".to_string(),
+            Ipld::Map(BTreeMap::from([(
+                "parents".to_string(),
+                Ipld::List(vec![parent]),
+            )])),
+        )]))
+    };
+    let root = Ipld::Map(BTreeMap::from([(
+        "entries".to_string(),
+        Ipld::List(vec![
+            entry(Ipld::Integer(1)),
+            entry(Ipld::String("a".to_string())),
+        ]),
+    )]));
+    let input = to_vec(&root).unwrap();
+
+    // Paths aren't tracked by default.
+    let error = de::from_slice::<Root>(&input).unwrap_err();
+    assert!(error.location().path().is_empty());
+
+    let options = de::DeserializerOptions::new().track_path(true);
+    let error = de::from_slice_with::<Root>(&input, options).unwrap_err();
+    assert_eq!(
+        error.location().path().to_string(),
+        "entries[1].header.parents[0]"
+    );
+    assert!(error.offset().is_some());
+    assert!(error.to_string().contains("entries[1].header.parents[0]"));
+
+    // Errors about keys are reported at the map that contains them.
+    let input = b"\xa1\x67entries\xa2\x61b\x80\x61a\x80";
+    let options = de::DeserializerOptions::strict().track_path(true);
+    let error = de::from_slice_with::<Ipld>(input, options).unwrap_err();
+    assert!(matches!(error, DecodeError::UnsortedMapKeys { .. }));
+    assert_eq!(error.location().path().to_string(), "entries");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_error_offsets_from_reader() {
+    // A non-finite float in a list: [1, NaN]
+    let input: &[u8] = b"\x82\x01\xfb\x7f\xf8\x00\x00\x00\x00\x00\x00";
+    let error = de::from_reader::<(u8, f64), _>(input).unwrap_err();
+    assert!(matches!(error, DecodeError::Mismatch { .. }));
+    assert_eq!(error.offset(), Some(2));
+    assert_eq!(error.location().window(), &input[2..]);
+
+    let error = de::from_reader::<(u8, u8), _>(&b"\x82\x01"[..]).unwrap_err();
+    assert!(matches!(error, DecodeError::Eof { .. }));
+    assert_eq!(error.offset(), Some(2));
+    assert!(error.location().window().is_empty());
+}
+
 #[test]
 fn truncated_object() {
     let input: Vec<u8> = [
@@ -571,7 +1323,7 @@ fn test_default_values() {
         TestCase {
             hex: "8318ca636e7570f4",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::LengthMismatch{ name, expect, value} if *name == "TupleWithDefaultsStruct" && *expect == 2 && *value == 3),
+                |err| matches!(err, DecodeError::LengthMismatch { name, expect, value, .. } if *name == "TupleWithDefaultsStruct" && *expect == 2 && *value == 3),
             ),
         },
     ];
@@ -605,7 +1357,7 @@ fn test_default_values() {
         TestCase {
             hex: "831901f98318ca636e7570f419025e",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::LengthMismatch{ name, expect, value} if *name == "TupleWithDefaultsStruct" && *expect == 2 && *value == 3),
+                |err| matches!(err, DecodeError::LengthMismatch { name, expect, value, .. } if *name == "TupleWithDefaultsStruct" && *expect == 2 && *value == 3),
             ),
         },
         // [505,[]]
@@ -662,7 +1414,7 @@ fn test_default_values() {
         TestCase {
             hex: "80",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::Msg(ref m) if m == "invalid length 0, expected tuple struct Inner with 2 elements"),
+                |err| matches!(err, DecodeError::Msg(ref m, _) if m == "invalid length 0, expected tuple struct Inner with 2 elements"),
             ),
         },
     ];
@@ -685,19 +1437,19 @@ fn test_default_values() {
         TestCase {
             hex: "828301020304",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::LengthMismatch{ name, expect, value} if *name == "TupleIntInner" && *expect == 2 && *value == 3),
+                |err| matches!(err, DecodeError::LengthMismatch { name, expect, value, .. } if *name == "TupleIntInner" && *expect == 2 && *value == 3),
             ),
         },
         // [[1,2]] + 3 -> error because there's a trailing element
         TestCase {
             hex: "8182010203",
-            expected: Expected::Err(|err| matches!(err, DecodeError::TrailingData)),
+            expected: Expected::Err(|err| matches!(err, DecodeError::TrailingData { .. })),
         },
         // [[1,2,3]] -> error because outer has too few elements
         TestCase {
             hex: "8183010203",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::LengthMismatch{ name, expect, value} if *name == "TupleIntInner" && *expect == 2 && *value == 3),
+                |err| matches!(err, DecodeError::LengthMismatch { name, expect, value, .. } if *name == "TupleIntInner" && *expect == 2 && *value == 3),
             ),
         },
     ];
@@ -715,26 +1467,26 @@ fn test_default_values() {
         TestCase {
             hex: "a261630365696e6e6572a1616101",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::Msg(ref m) if m == "missing field `b`"),
+                |err| matches!(err, DecodeError::Msg(ref m, _) if m == "missing field `b`"),
             ),
         },
         // {"inner":{"a":1,"b":2,"c":3},"c":4} -> error because inner has too many elements
         TestCase {
             hex: "a261630465696e6e6572a3616101616202616303",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::Msg(ref m) if m == "unknown field `c`, expected `a` or `b`"),
+                |err| matches!(err, DecodeError::Msg(ref m, _) if m == "unknown field `c`, expected `a` or `b`"),
             ),
         },
         // {"inner":{"a":1,"b":2}} + "c":3 -> error because there's a trailing element
         TestCase {
             hex: "a165696e6e6572a2616101616202616303",
-            expected: Expected::Err(|err| matches!(err, DecodeError::TrailingData)),
+            expected: Expected::Err(|err| matches!(err, DecodeError::TrailingData { .. })),
         },
         // {"inner":{"a":1,"b":2,"c":3}} -> error because outer has too few elements
         TestCase {
             hex: "a165696e6e6572a3616101616202616303",
             expected: Expected::Err(
-                |err| matches!(err, DecodeError::Msg(ref m) if m == "unknown field `c`, expected `a` or `b`"),
+                |err| matches!(err, DecodeError::Msg(ref m, _) if m == "unknown field `c`, expected `a` or `b`"),
             ),
         },
     ];
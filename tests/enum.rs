@@ -83,5 +83,8 @@ enum Foo {
 fn test_variable_length_array_error() {
     let slice = b"\x9F\x67\x72\x65\x71\x75\x69\x72\x65\xFF";
     let value: Result<Vec<Foo>, _> = from_slice(slice);
-    assert!(matches!(value.unwrap_err(), DecodeError::IndefiniteSize));
+    assert!(matches!(
+        value.unwrap_err(),
+        DecodeError::IndefiniteSize { .. }
+    ));
 }
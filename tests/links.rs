@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use ipld_core::{cid::Cid, ipld::Ipld};
+use serde_ipld_dagcbor::{
+    de::DeserializerOptions,
+    error::{InvalidCidReason, PathSegment, RewriteError},
+    links::{rewrite_links, rewrite_links_with, walk_links, walk_links_with, LinkScanner},
+    to_vec, DecodeError,
+};
+
+#[test]
+fn test_walk_links() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let cid_v0 = Cid::from_str("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+    let mut header = BTreeMap::new();
+    header.insert(
+        "parents".to_string(),
+        Ipld::List(vec![Ipld::Link(cid), Ipld::Link(cid_v0)]),
+    );
+    header.insert("height".to_string(), Ipld::Integer(7));
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), Ipld::Link(cid_v0));
+    map.insert(
+        "entries".to_string(),
+        Ipld::List(vec![Ipld::Null, Ipld::Map(header)]),
+    );
+    map.insert("bytes".to_string(), Ipld::Bytes(vec![0x00, 0x01]));
+    let encoded = to_vec(&Ipld::Map(map)).unwrap();
+
+    let mut found = Vec::new();
+    walk_links(&encoded, |path, cid, offset| {
+        found.push((path.to_string(), cid, offset));
+    })
+    .unwrap();
+    let paths: Vec<_> = found
+        .iter()
+        .map(|(path, cid, _)| (&path[..], *cid))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            ("a", cid_v0),
+            ("entries[1].parents[0]", cid),
+            ("entries[1].parents[1]", cid_v0),
+        ]
+    );
+    // Every offset points to the tag of the link.
+    for (_, cid, offset) in found {
+        assert_eq!(&encoded[offset..offset + 2], b"\xd8\x2a");
+        assert_eq!(
+            to_vec(&Ipld::Link(cid)).unwrap()[..],
+            encoded[offset..][..cid.encoded_len() + 5]
+        );
+    }
+}
+
+#[test]
+fn test_walk_links_path_segments() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let encoded = to_vec(&Ipld::List(vec![Ipld::Link(cid)])).unwrap();
+
+    let mut segments = Vec::new();
+    walk_links(&encoded, |path, _, offset| {
+        segments.push((path.segments().to_vec(), offset));
+    })
+    .unwrap();
+    assert_eq!(segments, [(vec![PathSegment::Index(0)], 1)]);
+
+    // A link at the top level has an empty path.
+    let encoded = to_vec(&Ipld::Link(cid)).unwrap();
+    let mut count = 0;
+    walk_links(&encoded, |path, _, offset| {
+        assert!(path.is_empty());
+        assert_eq!(offset, 0);
+        count += 1;
+    })
+    .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn test_walk_links_options() {
+    // {"b": tag 42 with bytes that aren't a CID, "a": 1}
+    let input = b"\xa2\x61b\xd8\x2a\x43\x00\x01\x02\x61a\x01";
+
+    let error = walk_links(input, |_, _, _| panic!("no valid link")).unwrap_err();
+    assert!(matches!(error, DecodeError::Msg(..)));
+
+    let options = DeserializerOptions::new().validate_cids(true);
+    let error = walk_links_with(input, options, |_, _, _| panic!("no valid link")).unwrap_err();
+    assert!(matches!(
+        error,
+        DecodeError::InvalidCid {
+            reason: InvalidCidReason::Truncated,
+            ..
+        }
+    ));
+    assert_eq!(error.offset(), Some(9));
+
+    // Unsorted map keys are only rejected with the strict options.
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let link = to_vec(&Ipld::Link(cid)).unwrap();
+    let input = [&b"\xa2\x61b"[..], &link, b"\x61a\x01"].concat();
+    let mut count = 0;
+    walk_links(&input, |_, _, _| count += 1).unwrap();
+    assert_eq!(count, 1);
+    let error = walk_links_with(&input, DeserializerOptions::strict(), |_, _, _| {}).unwrap_err();
+    assert_eq!(error.offset(), Some(3 + link.len()));
+
+    // Trailing data.
+    let input = [&link[..], &[0x01]].concat();
+    let error = walk_links(&input, |_, _, _| {}).unwrap_err();
+    assert!(matches!(error, DecodeError::TrailingData { .. }));
+}
+
+#[test]
+fn test_link_scanner() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let cid_v0 = Cid::from_str("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+    let mut map = BTreeMap::new();
+    map.insert("bytes".to_string(), Ipld::Bytes(vec![0xd8, 0x2a]));
+    map.insert("link".to_string(), Ipld::Link(cid_v0));
+    map.insert("float".to_string(), Ipld::Float(-0.5));
+    map.insert("string".to_string(), Ipld::String("\u{d8}*".to_string()));
+    // Values that are strings, but no map keys.
+    let nested = Ipld::List(vec![
+        Ipld::String("a".to_string()),
+        Ipld::Map(BTreeMap::new()),
+        Ipld::String("b".to_string()),
+    ]);
+    map.insert(
+        "nested".to_string(),
+        Ipld::Map(BTreeMap::from([
+            ("list".to_string(), nested),
+            ("tail".to_string(), Ipld::Link(cid)),
+        ])),
+    );
+    let data = Ipld::List(vec![
+        Ipld::Link(cid),
+        Ipld::Map(map),
+        Ipld::List(vec![]),
+        Ipld::Integer(-1_000_000_000_000),
+        Ipld::Link(cid),
+    ]);
+    let encoded = to_vec(&data).unwrap();
+
+    let links = LinkScanner::from_slice(&encoded)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(links, [cid, cid_v0, cid, cid]);
+
+    // Without links.
+    let encoded = to_vec(&Ipld::String("no links".to_string())).unwrap();
+    assert_eq!(LinkScanner::from_slice(&encoded).count(), 0);
+}
+
+#[test]
+fn test_link_scanner_nesting() {
+    // The nesting depth isn't limited, as there is no recursion.
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let mut encoded = vec![0x81; 100_000];
+    encoded.extend(to_vec(&Ipld::Link(cid)).unwrap());
+    let links = LinkScanner::from_slice(&encoded)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(links, [cid]);
+}
+
+#[test]
+fn test_link_scanner_errors() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let link = to_vec(&Ipld::Link(cid)).unwrap();
+
+    let cases: Vec<(Vec<u8>, usize)> = vec![
+        // Tag other than 42.
+        ([&b"\x82"[..], &link, b"\xc1\x01"].concat(), 1 + link.len()),
+        // Indefinite length map.
+        (b"\x82\x01\xbf\xff".to_vec(), 2),
+        // Undefined.
+        ([&b"\x82"[..], &link, b"\xf7"].concat(), 1 + link.len()),
+        // Truncated string.
+        (b"\x82\x01\x63ab".to_vec(), 5),
+        // Truncated list.
+        ([&b"\x83"[..], &link].concat(), 1 + link.len()),
+        // Trailing data.
+        ([&link[..], b"\x01"].concat(), link.len()),
+        // Integer map key.
+        ([&b"\xa1\x01"[..], &link].concat(), 1),
+        // Map key of invalid UTF-8.
+        (b"\xa1\x61\xff\x01".to_vec(), 1),
+        // Integer map key after a list value.
+        ([&b"\xa2\x61a\x82\x01\x02\x01"[..], &link].concat(), 6),
+    ];
+    for (input, offset) in cases {
+        let mut scanner = LinkScanner::from_slice(&input);
+        let results: Vec<_> = scanner.by_ref().collect();
+        assert!(
+            results.iter().take(results.len() - 1).all(Result::is_ok),
+            "for input {:02x?}",
+            input
+        );
+        let error = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(error.offset(), Some(offset), "for input {:02x?}", input);
+        assert!(scanner.next().is_none());
+    }
+
+    // Non-minimal integers are only rejected with the strict options, the links before the
+    // violation are returned.
+    let input = [&b"\x82"[..], &link, b"\x18\x01"].concat();
+    assert_eq!(LinkScanner::from_slice(&input).count(), 1);
+    let results: Vec<_> = LinkScanner::from_slice(&input)
+        .with_options(DeserializerOptions::strict())
+        .collect();
+    assert!(matches!(
+        results[..],
+        [Ok(_), Err(DecodeError::NonMinimalEncoding { .. })]
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_link_scanner_reader() {
+    use std::io::{BufReader, Read};
+
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let data = Ipld::List(vec![
+        Ipld::Bytes(vec![0xd8; 10_000]),
+        Ipld::Link(cid),
+        Ipld::String("x".repeat(10_000)),
+        Ipld::Link(cid),
+    ]);
+    let block = to_vec(&data).unwrap();
+
+    // A tiny buffer makes the strings and CIDs span several reads.
+    let links = LinkScanner::from_reader(BufReader::with_capacity(7, &block[..]))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(links, [cid, cid]);
+
+    // Scan the first of two concatenated blocks.
+    let stream = [&block[..], &block].concat();
+    let mut reader = &stream[..];
+    let links = LinkScanner::from_reader((&mut reader).take(block.len() as u64))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(links.len(), 2);
+    assert_eq!(reader, &block[..]);
+
+    // The links before an error are returned.
+    let mut scanner = LinkScanner::from_reader(&block[..block.len() - 1]);
+    assert_eq!(scanner.next().unwrap().unwrap(), cid);
+    let error = scanner.next().unwrap().unwrap_err();
+    assert!(matches!(error, DecodeError::Eof { .. }));
+    assert!(scanner.next().is_none());
+}
+
+#[test]
+fn test_rewrite_links() {
+    let cid_v0 = Cid::from_str("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    // A CID with an identity hash, which encodes to less than 24 bytes.
+    let small = Cid::try_from(&b"\x01\x55\x00\x02hi"[..]).unwrap();
+
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), Ipld::Link(cid_v0));
+    map.insert(
+        "bb".to_string(),
+        Ipld::List(vec![Ipld::Link(cid), Ipld::Integer(1)]),
+    );
+    map.insert("ccc".to_string(), Ipld::Link(small));
+    map.insert("dddd".to_string(), Ipld::String("text".to_string()));
+    let data = Ipld::Map(map.clone());
+    let encoded = to_vec(&data).unwrap();
+
+    let mut seen = Vec::new();
+    let rewritten = rewrite_links(&encoded, |link| {
+        seen.push(*link);
+        Ok::<_, ()>(if *link == cid_v0 {
+            link.into_v1().unwrap()
+        } else if *link == cid {
+            small
+        } else {
+            cid
+        })
+    })
+    .unwrap();
+    assert_eq!(seen, [cid_v0, cid, small]);
+
+    map.insert("a".to_string(), Ipld::Link(cid_v0.into_v1().unwrap()));
+    map.insert(
+        "bb".to_string(),
+        Ipld::List(vec![Ipld::Link(small), Ipld::Integer(1)]),
+    );
+    map.insert("ccc".to_string(), Ipld::Link(cid));
+    let expected = to_vec(&Ipld::Map(map)).unwrap();
+    assert_eq!(rewritten, expected);
+    // The result is valid DAG-CBOR, with the map keys still in order.
+    let options = DeserializerOptions::strict();
+    walk_links_with(&rewritten, options, |_, _, _| {}).unwrap();
+
+    // Unchanged links are copied as they are.
+    let unchanged = rewrite_links(&encoded, |link| Ok::<_, ()>(*link)).unwrap();
+    assert_eq!(unchanged, encoded);
+}
+
+#[test]
+fn test_rewrite_links_copies_bytes() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let small = Cid::try_from(&b"\x01\x55\x00\x02hi"[..]).unwrap();
+    let link = to_vec(&Ipld::Link(cid)).unwrap();
+    // [non-minimal 1, unsorted {"b": link, "a": 2.5 as f32}]
+    let input = [
+        &b"\x82\x18\x01\xa2\x61b"[..],
+        &link,
+        b"\x61a\xfa\x40\x20\x00\x00",
+    ]
+    .concat();
+    let options = DeserializerOptions::lenient();
+
+    let rewritten = rewrite_links_with(&input, options, |_| Ok::<_, ()>(small)).unwrap();
+    let small_link = to_vec(&Ipld::Link(small)).unwrap();
+    let expected = [
+        &b"\x82\x18\x01\xa2\x61b"[..],
+        &small_link,
+        b"\x61a\xfa\x40\x20\x00\x00",
+    ]
+    .concat();
+    assert_eq!(rewritten, expected);
+    let decoded: Ipld = serde_ipld_dagcbor::de::from_slice_lenient(&rewritten)
+        .unwrap()
+        .0;
+    assert_eq!(
+        decoded,
+        Ipld::List(vec![
+            Ipld::Integer(1),
+            Ipld::Map(
+                vec![
+                    ("a".to_string(), Ipld::Float(2.5)),
+                    ("b".to_string(), Ipld::Link(small)),
+                ]
+                .into_iter()
+                .collect()
+            ),
+        ])
+    );
+}
+
+#[test]
+fn test_rewrite_links_errors() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let encoded = to_vec(&Ipld::List(vec![Ipld::Link(cid), Ipld::Link(cid)])).unwrap();
+
+    // The first failed rewrite is returned, no further links are passed on.
+    let mut calls = 0;
+    let error = rewrite_links(&encoded, |_| {
+        calls += 1;
+        Err::<Cid, _>(calls)
+    })
+    .unwrap_err();
+    assert!(matches!(error, RewriteError::Rewrite(1)));
+    assert_eq!(calls, 1);
+    assert_eq!(error.to_string(), "failed to rewrite link: 1");
+
+    let error =
+        rewrite_links(&encoded[..encoded.len() - 1], |link| Ok::<_, ()>(*link)).unwrap_err();
+    assert!(matches!(
+        error,
+        RewriteError::Decode(DecodeError::Eof { .. })
+    ));
+
+    // Map keys must be strings, so that rewriting can't change their order.
+    let link = to_vec(&Ipld::Link(cid)).unwrap();
+    let input = [&b"\xa1"[..], &link, b"\x01"].concat();
+    let error = rewrite_links(&input, |link| Ok::<_, ()>(*link)).unwrap_err();
+    assert!(matches!(error, RewriteError::Decode(_)));
+}
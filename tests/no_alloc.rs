@@ -0,0 +1,150 @@
+//! Encoding into a slice and scanning for links must not allocate, this is checked with a
+//! counting allocator.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+use ipld_core::cid::serde::CID_SERDE_PRIVATE_IDENTIFIER;
+use serde::ser::{Serialize, Serializer};
+use serde_bytes::Bytes;
+use serde_derive::Serialize;
+use serde_ipld_dagcbor::{ser, to_vec};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[derive(Serialize)]
+struct Reading {
+    sensor: &'static str,
+    values: [f64; 3],
+    offset: i32,
+}
+
+/// A CID that serializes its bytes without allocating, contrary to `Cid`.
+struct Link(&'static [u8]);
+
+impl Serialize for Link {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(CID_SERDE_PRIVATE_IDENTIFIER, Bytes::new(self.0))
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    readings: Vec<Reading>,
+    labels: BTreeMap<i32, &'static str>,
+    previous: Link,
+}
+
+#[test]
+fn test_to_slice_does_not_allocate() {
+    let mut labels = BTreeMap::new();
+    labels.insert(100, "hundred");
+    labels.insert(-1, "minus one");
+    let report = Report {
+        readings: vec![
+            Reading {
+                sensor: "temperature",
+                values: [21.5, 21.7, 21.6],
+                offset: -3,
+            },
+            Reading {
+                sensor: "humidity",
+                values: [0.4, 0.5, 0.45],
+                offset: 0,
+            },
+        ],
+        labels,
+        // bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy
+        previous: Link(
+            b"\x01\x55\x12\x20\x2c\x26\xb4\x6b\x68\xff\xc6\x8f\xf9\x9b\x45\x3c\x1d\x30\x41\x34\x13\x42\x2d\x70\x64\x83\xbf\xa0\xf9\x8a\x5e\x88\x62\x66\xe7\xae",
+        ),
+    };
+    let options = ser::SerializerOptions::new().stringify_integer_keys(true);
+    let expected = ser::to_vec_with(&report, options).unwrap();
+    let mut buf = [0; 256];
+
+    let before = allocations();
+    let len = ser::to_slice_with(&mut buf, &report, options).unwrap();
+    assert_eq!(allocations(), before);
+    assert_eq!(&buf[..len], &expected[..]);
+
+    // Buffering with the default serializer does allocate.
+    let before = allocations();
+    to_vec(&report.readings).unwrap();
+    assert!(allocations() > before);
+}
+
+#[test]
+fn test_encoder_steady_state_does_not_allocate() {
+    let mut encoder = ser::Encoder::new();
+    let mut buf = Vec::new();
+    let records: Vec<BTreeMap<&str, Vec<u32>>> = (0..100)
+        .map(|i| {
+            let mut record = BTreeMap::new();
+            record.insert("id", vec![i]);
+            record.insert("parents", (0..i % 10).collect());
+            record
+        })
+        .collect();
+    // The first records grow the buffers.
+    for record in &records {
+        buf.clear();
+        encoder.encode_into(&mut buf, record).unwrap();
+    }
+
+    let before = allocations();
+    for record in &records {
+        buf.clear();
+        encoder.encode_into(&mut buf, record).unwrap();
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(buf, to_vec(&records[99]).unwrap());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_link_scanner_reader_does_not_allocate() {
+    use std::str::FromStr;
+
+    use ipld_core::{cid::Cid, ipld::Ipld};
+    use serde_ipld_dagcbor::links::LinkScanner;
+
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let mut map = BTreeMap::new();
+    map.insert("data".to_string(), Ipld::Bytes(vec![0x2a; 1000]));
+    map.insert("name".to_string(), Ipld::String("block".to_string()));
+    map.insert("links".to_string(), Ipld::List(vec![Ipld::Link(cid); 10]));
+    let block = to_vec(&Ipld::Map(map)).unwrap();
+    let reader = std::io::BufReader::with_capacity(16, &block[..]);
+
+    let before = allocations();
+    let mut count = 0;
+    for link in LinkScanner::from_reader(reader) {
+        assert_eq!(link.unwrap(), cid);
+        count += 1;
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(count, 10);
+}
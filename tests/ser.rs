@@ -1,13 +1,14 @@
 use std::str::FromStr;
 use std::{collections::BTreeMap, iter};
 
-use ipld_core::cid::Cid;
+use ipld_core::{cid::Cid, ipld::Ipld};
 use serde::de::value::{self, MapDeserializer, SeqDeserializer};
 use serde_bytes::{ByteBuf, Bytes};
 use serde_derive::Serialize;
 use serde_ipld_dagcbor::{
+    error::BufferFull,
     from_slice,
-    ser::{BufWriter, Serializer},
+    ser::{self, BufWriter, Serializer, SerializerOptions},
     to_vec, EncodeError,
 };
 
@@ -275,7 +276,7 @@ fn test_non_string_map_keys_rejected() {
         map.insert(key, 0u8);
         let err = to_vec(&map).unwrap_err();
         assert!(
-            matches!(&err, EncodeError::Msg(msg) if msg.contains("Map keys must be strings")),
+            matches!(&err, EncodeError::Msg(msg, _) if msg.contains("Map keys must be strings")),
             "{}: unexpected error: {:?}",
             what,
             err
@@ -294,3 +295,639 @@ fn test_non_string_map_keys_rejected() {
         "CID (tag 42)",
     );
 }
+
+#[test]
+fn test_nested_containers_canonical() {
+    #[derive(Serialize)]
+    enum Variant {
+        Struct { long: u8, a: Unsized<u8> },
+    }
+
+    #[derive(Serialize)]
+    struct Inner {
+        bb: u8,
+        a: Unsized<BTreeMap<&'static str, u8>>,
+        variant: Variant,
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert("c", 1);
+    map.insert("aa", 2);
+    let inner = Inner {
+        bb: 1,
+        a: Unsized(vec![map.clone(), BTreeMap::new()]),
+        variant: Variant::Struct {
+            long: 2,
+            a: Unsized(vec![3]),
+        },
+    };
+    let mut outer = BTreeMap::new();
+    outer.insert("zz", Unsized(vec![inner]));
+    outer.insert("b", Unsized(Vec::new()));
+
+    let encoded = to_vec(&outer).unwrap();
+    let expected = b"\xa2\
+        \x61b\x80\
+        \x62zz\x81\xa3\
+            \x61a\x82\xa2\x61c\x01\x62aa\x02\xa0\
+            \x62bb\x01\
+            \x67variant\xa1\x66Struct\xa2\x61a\x81\x03\x64long\x02";
+    assert_eq!(encoded, &expected[..]);
+
+    // The serializer can be reused, the output of one value doesn't leak into the next one.
+    let mut serializer = Serializer::new(BufWriter::new(Vec::new()));
+    serde::Serialize::serialize(&outer, &mut serializer).unwrap();
+    serde::Serialize::serialize(&map, &mut serializer).unwrap();
+    let result = serializer.into_inner().into_inner();
+    assert_eq!(&result[..expected.len()], &expected[..]);
+    assert_eq!(&result[expected.len()..], b"\xa2\x61c\x01\x62aa\x02");
+}
+
+#[test]
+fn test_deeply_nested_maps() {
+    let mut value = Ipld::Integer(0);
+    for depth in 0..100 {
+        let mut map = BTreeMap::new();
+        map.insert("x".repeat(depth % 7 + 1), value);
+        map.insert("y".to_string(), Ipld::Integer(depth as i128));
+        value = Ipld::Map(map);
+    }
+    let encoded = to_vec(&value).unwrap();
+    let decoded: Ipld = serde_ipld_dagcbor::de::from_slice_strict(&encoded).unwrap();
+    assert_eq!(decoded, value);
+}
+
+/// A sequence of unknown length.
+struct Unsized<T>(Vec<T>);
+
+impl<T: serde::Serialize> serde::Serialize for Unsized<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().filter(|_| true))
+    }
+}
+
+#[test]
+fn test_unsized_sequences() {
+    /// Checks that the value is encoded like the same value with known lengths.
+    fn check<T: serde::Serialize, S: serde::Serialize>(value: &T, sized: &S) {
+        let expected = to_vec(sized).unwrap();
+        assert_eq!(to_vec(value).unwrap(), expected);
+
+        // Without backpatching, sequences of unknown length are buffered.
+        let mut serializer = Serializer::new(BufWriter::new(Vec::new()));
+        value.serialize(&mut serializer).unwrap();
+        assert_eq!(serializer.into_inner().into_inner(), expected);
+
+        #[cfg(feature = "std")]
+        {
+            // Writing at an offset into existing data.
+            let mut cursor = std::io::Cursor::new(b"prefix".to_vec());
+            cursor.set_position(6);
+            ser::to_seekable_writer(&mut cursor, value).unwrap();
+            ser::to_seekable_writer(&mut cursor, value).unwrap();
+            let result = cursor.into_inner();
+            assert_eq!(&result[..6], b"prefix");
+            assert_eq!(&result[6..6 + expected.len()], &expected[..]);
+            assert_eq!(&result[6 + expected.len()..], &expected[..]);
+        }
+    }
+
+    check(&Unsized(Vec::<u8>::new()), &Vec::<u8>::new());
+    check(&Unsized(vec![1u8; 30]), &vec![1u8; 30]);
+
+    let mut map = BTreeMap::new();
+    map.insert("bb", Unsized(vec![1u16; 30]));
+    map.insert("a", Unsized(vec![]));
+    let mut sized_map = BTreeMap::new();
+    sized_map.insert("bb", vec![1u16; 30]);
+    sized_map.insert("a", vec![]);
+    check(
+        &Unsized(vec![Unsized(vec![map]), Unsized(vec![])]),
+        &vec![vec![sized_map], vec![]],
+    );
+
+    let lengths = (0..1000).map(|i| i % 30);
+    check(
+        &Unsized(lengths.clone().map(|len| Unsized(vec![len; len])).collect()),
+        &lengths.map(|len| vec![len; len]).collect::<Vec<_>>(),
+    );
+}
+
+#[test]
+fn test_unsized_sequences_max_output_size() {
+    let value = Unsized(vec![1u8; 30]);
+    let encoded = to_vec(&value).unwrap();
+    assert_eq!(encoded.len(), 32);
+    let options = SerializerOptions::new().max_output_size(31);
+    let err = ser::to_vec_with(&value, options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 31, .. }));
+}
+
+#[test]
+fn test_encoded_len() {
+    fn check<T: serde::Serialize>(value: &T) {
+        assert_eq!(
+            ser::encoded_len(value).unwrap(),
+            to_vec(value).unwrap().len()
+        );
+    }
+
+    #[derive(Serialize)]
+    enum Variant {
+        Struct { long: u8, a: Unsized<u8> },
+        Tuple(u8, String),
+        Newtype(Vec<u8>),
+    }
+
+    check(&0u8);
+    check(&"a".repeat(300));
+    check(&ByteBuf::from(vec![0; 70_000]));
+    check(&Unsized(
+        (0..1000).map(|i| Unsized(vec![i; i % 30])).collect(),
+    ));
+    check(&vec![
+        Variant::Struct {
+            long: 1,
+            a: Unsized(vec![1; 24]),
+        },
+        Variant::Tuple(1, "a".into()),
+        Variant::Newtype(vec![]),
+    ]);
+    check(&Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap());
+
+    let mut value = Ipld::Integer(0);
+    for depth in 0..100 {
+        let mut map = BTreeMap::new();
+        map.insert("x".repeat(depth % 30 + 1), value);
+        map.insert(
+            "y".to_string(),
+            Ipld::List(vec![Ipld::Integer(depth as i128); depth]),
+        );
+        value = Ipld::Map(map);
+    }
+    check(&value);
+
+    // The same values fail.
+    let mut map = BTreeMap::new();
+    map.insert(1, 1);
+    assert!(ser::encoded_len(&map).is_err());
+    let mut map = BTreeMap::new();
+    map.insert("a", vec![f64::NAN]);
+    assert!(ser::encoded_len(&map).is_err());
+}
+
+#[test]
+fn test_encoded_len_with() {
+    let map: BTreeMap<u32, _> = (0..300).map(|i| (i * 7, vec![i; 3])).collect();
+    let options = SerializerOptions::new().stringify_integer_keys(true);
+    assert_eq!(
+        ser::encoded_len_with(&map, options).unwrap(),
+        ser::to_vec_with(&map, options).unwrap().len()
+    );
+
+    // The limits apply too.
+    let len = ser::encoded_len_with(&map, options).unwrap();
+    let err = ser::encoded_len_with(&map, options.max_output_size(len - 1)).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { .. }));
+    let err = ser::encoded_len_with(&map, options.max_depth(1)).unwrap_err();
+    assert!(matches!(err, EncodeError::DepthOverflow { .. }));
+}
+
+#[test]
+fn test_options_max_output_size() {
+    let value = vec!["a".repeat(10), "b".repeat(10)];
+    let encoded = to_vec(&value).unwrap();
+    assert_eq!(encoded.len(), 23);
+
+    let options = SerializerOptions::new().max_output_size(23);
+    assert_eq!(ser::to_vec_with(&value, options).unwrap(), encoded);
+
+    let options = SerializerOptions::new().max_output_size(22);
+    let err = ser::to_vec_with(&value, options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 22, .. }));
+
+    // Map entries and sequences of unknown length are buffered, which counts towards the limit.
+    let mut map = BTreeMap::new();
+    map.insert("key", vec![1u8; 100]);
+    let encoded = to_vec(&map).unwrap();
+    let options = SerializerOptions::new().max_output_size(encoded.len());
+    assert_eq!(ser::to_vec_with(&map, options).unwrap(), encoded);
+    let options = SerializerOptions::new().max_output_size(50);
+    let err = ser::to_vec_with(&map, options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 50, .. }));
+
+    struct Unbounded;
+    impl serde::Serialize for Unbounded {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq((0..100u8).filter(|_| true))
+        }
+    }
+    let options = SerializerOptions::new().max_output_size(50);
+    let err = ser::to_vec_with(&Unbounded, options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 50, .. }));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_options_max_output_size_writer() {
+    let mut output = Vec::new();
+    let options = SerializerOptions::new().max_output_size(5);
+    let err = ser::to_writer_with(&mut output, &"abcdef", options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 5, .. }));
+    // Nothing beyond the limit is written.
+    assert!(output.len() <= 5);
+
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    let err =
+        ser::to_seekable_writer_with(&mut cursor, &Unsized(vec![1u8; 30]), options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 5, .. }));
+    assert!(cursor.into_inner().len() <= 5);
+
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    let options = SerializerOptions::new().stringify_integer_keys(true);
+    let map = BTreeMap::from([(1, 2)]);
+    ser::to_seekable_writer_with(&mut cursor, &map, options).unwrap();
+    assert_eq!(
+        cursor.into_inner(),
+        ser::to_vec_with(&map, options).unwrap()
+    );
+}
+
+#[test]
+fn test_options_max_depth() {
+    #[derive(Serialize)]
+    struct Nested {
+        list: Vec<Vec<u8>>,
+    }
+
+    let value = Nested {
+        list: vec![vec![1]],
+    };
+    let options = SerializerOptions::new().max_depth(3);
+    assert_eq!(
+        ser::to_vec_with(&value, options).unwrap(),
+        to_vec(&value).unwrap()
+    );
+    let options = SerializerOptions::new().max_depth(2);
+    let err = ser::to_vec_with(&value, options).unwrap_err();
+    assert!(matches!(err, EncodeError::DepthOverflow { limit: 2, .. }));
+
+    let nested = vec![vec![vec![vec![1u8]]]];
+    let options = SerializerOptions::new().max_depth(3);
+    let err = ser::to_vec_with(&nested, options).unwrap_err();
+    assert!(matches!(err, EncodeError::DepthOverflow { limit: 3, .. }));
+
+    // The serializer can be used again after the value was encoded.
+    let mut serializer = Serializer::new(BufWriter::new(Vec::new()))
+        .with_options(SerializerOptions::new().max_depth(1));
+    for _ in 0..3 {
+        serde::Serialize::serialize(&vec![1u8], &mut serializer).unwrap();
+    }
+}
+
+#[test]
+fn test_options_stringify_integer_keys() {
+    let mut map = BTreeMap::new();
+    map.insert(-1i64, "minus one");
+    map.insert(10, "ten");
+    map.insert(9, "nine");
+    assert!(to_vec(&map).is_err());
+
+    let options = SerializerOptions::new().stringify_integer_keys(true);
+    let encoded = ser::to_vec_with(&map, options).unwrap();
+    let decoded: BTreeMap<String, String> = from_slice(&encoded).unwrap();
+    assert_eq!(decoded["-1"], "minus one");
+    assert_eq!(decoded["10"], "ten");
+    assert_eq!(decoded["9"], "nine");
+    // Stringified keys are sorted like all other keys.
+    assert_eq!(&encoded[..3], b"\xa3\x619");
+
+    // Other keys are still rejected.
+    let mut map = BTreeMap::new();
+    map.insert(true, 1);
+    assert!(ser::to_vec_with(&map, options).is_err());
+}
+
+#[test]
+fn test_duplicate_map_keys_rejected() {
+    struct Duplicates;
+    impl serde::Serialize for Duplicates {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(vec![("a", 1), ("b", 2), ("a", 3)])
+        }
+    }
+
+    let err = to_vec(&Duplicates).unwrap_err();
+    assert!(matches!(&err, EncodeError::DuplicateMapKey { key, .. } if key == "a"));
+    assert_eq!(err.to_string(), "duplicate map key \"a\"");
+    let err = ser::encoded_len(&Duplicates).unwrap_err();
+    assert!(matches!(&err, EncodeError::DuplicateMapKey { key, .. } if key == "a"));
+
+    // Within nested maps and sequences of unknown length.
+    let mut map = BTreeMap::new();
+    map.insert("outer", Unsized(vec![Duplicates]));
+    let err = to_vec(&map).unwrap_err();
+    assert!(matches!(&err, EncodeError::DuplicateMapKey { key, .. } if key == "a"));
+
+    // Stringified integer keys may collide with string keys.
+    struct Colliding;
+    impl serde::Serialize for Colliding {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeMap;
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry(&1, &1)?;
+            map.serialize_entry("1", &1)?;
+            map.end()
+        }
+    }
+    let options = SerializerOptions::new().stringify_integer_keys(true);
+    let err = ser::to_vec_with(&Colliding, options).unwrap_err();
+    assert!(matches!(&err, EncodeError::DuplicateMapKey { key, .. } if key == "1"));
+
+    // The check can be disabled.
+    let options = SerializerOptions::new().check_duplicate_keys(false);
+    assert!(ser::to_vec_with(&Duplicates, options).is_ok());
+}
+
+#[test]
+fn test_nested_errors_preserved() {
+    struct Failing;
+    impl serde::Serialize for Failing {
+        fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("not today"))
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Sample {
+        value: f64,
+    }
+
+    #[derive(Serialize)]
+    struct Metrics {
+        samples: Vec<Sample>,
+    }
+
+    let metrics = Metrics {
+        samples: vec![Sample { value: 1.0 }, Sample { value: f64::NAN }],
+    };
+    let err = to_vec(&metrics).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Float must be a finite number, not Infinity or NaN in samples[1].value"
+    );
+
+    let mut map = BTreeMap::new();
+    map.insert("key", Unsized(vec![Failing]));
+    let err = to_vec(&map).unwrap_err();
+    assert!(matches!(&err, EncodeError::Msg(msg, _) if msg == "not today"));
+    let err = ser::encoded_len(&map).unwrap_err();
+    assert!(matches!(&err, EncodeError::Msg(msg, _) if msg == "not today"));
+
+    let mut map = BTreeMap::new();
+    map.insert(vec![1u8], 1u8);
+    let err = to_vec(&map).unwrap_err();
+    assert_eq!(err.to_string(), "Map keys must be strings");
+}
+
+#[test]
+fn test_error_paths() {
+    fn path<T: serde::Serialize>(value: &T) -> String {
+        let err = to_vec(value).unwrap_err();
+        let path = err.path().to_string();
+        assert_eq!(
+            ser::encoded_len(value).unwrap_err().path().to_string(),
+            path
+        );
+        path
+    }
+
+    #[derive(Serialize)]
+    enum Value {
+        Float(f64),
+        Pair(u8, f64),
+        Sample { value: f64 },
+    }
+
+    #[derive(Serialize)]
+    struct Metrics {
+        name: &'static str,
+        samples: Vec<Value>,
+        labels: BTreeMap<&'static str, (u8, Value)>,
+    }
+
+    let mut metrics = Metrics {
+        name: "metrics",
+        samples: vec![Value::Float(1.0), Value::Sample { value: f64::NAN }],
+        labels: BTreeMap::new(),
+    };
+    assert_eq!(path(&metrics), "samples[1].value");
+    metrics.samples = vec![Value::Pair(1, f64::INFINITY)];
+    assert_eq!(path(&metrics), "samples[0][1]");
+    metrics.samples.clear();
+    metrics
+        .labels
+        .insert("a", (1, Value::Float(f64::NEG_INFINITY)));
+    assert_eq!(path(&metrics), "labels.a[1]");
+
+    // Sequences of unknown length.
+    let mut map = BTreeMap::new();
+    map.insert("outer", Unsized(vec![Unsized(vec![1.0, f64::NAN])]));
+    let err = to_vec(&map).unwrap_err();
+    assert!(matches!(err, EncodeError::Msg(_, _)));
+    assert_eq!(err.path().segments().len(), 3);
+    assert_eq!(
+        err.to_string(),
+        "Float must be a finite number, not Infinity or NaN in outer[0][1]"
+    );
+
+    // The path points to the map with duplicate keys, or the value that is too deep.
+    struct Duplicates;
+    impl serde::Serialize for Duplicates {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(vec![("a", 1), ("a", 2)])
+        }
+    }
+    assert_eq!(path(&vec![Duplicates]), "[0]");
+    let options = SerializerOptions::new().max_depth(2);
+    let err = ser::to_vec_with(&vec![vec![vec![1]]], options).unwrap_err();
+    assert!(matches!(err, EncodeError::DepthOverflow { limit: 2, .. }));
+    assert_eq!(err.path().to_string(), "[0][0]");
+
+    // Errors of the top-level value have an empty path.
+    let err = to_vec(&f64::NAN).unwrap_err();
+    assert!(err.path().is_empty());
+    assert_eq!(
+        err.to_string(),
+        "Float must be a finite number, not Infinity or NaN"
+    );
+}
+
+#[test]
+fn test_to_slice() {
+    /// Checks that the value is encoded into a slice like into a vector.
+    fn check<T: serde::Serialize>(value: &T, options: SerializerOptions) {
+        let expected = ser::to_vec_with(value, options).unwrap();
+        let mut buf = vec![0xff; expected.len() + 8];
+        let len = ser::to_slice_with(&mut buf, value, options).unwrap();
+        assert_eq!(&buf[..len], &expected[..]);
+        // The output fits exactly, but not into a byte less.
+        let len = ser::to_slice_with(&mut buf[..expected.len()], value, options).unwrap();
+        assert_eq!(len, expected.len());
+        let err = ser::to_slice_with(&mut buf[..expected.len() - 1], value, options).unwrap_err();
+        assert!(matches!(
+            err,
+            EncodeError::Write(serde_ipld_dagcbor::error::BufferFull)
+        ));
+    }
+    let options = SerializerOptions::new();
+
+    check(&"foobar", options);
+    check(&Unsized(vec![1u8; 30]), options);
+
+    // Maps in reverse, random and canonical order, with nested maps and sequences.
+    let keys: Vec<String> = (0..300)
+        .map(|i| "k".repeat(i % 7 + 1) + &i.to_string())
+        .collect();
+    let map: BTreeMap<_, _> = keys.iter().map(|key| (key.as_str(), key.len())).collect();
+    check(&map, options);
+    check(&reversed(&map), options);
+    let shuffled = (0..keys.len()).map(|i| (keys[i * 7 % keys.len()].as_str(), i));
+    check(&Entries(shuffled.collect()), options);
+
+    let mut nested = BTreeMap::new();
+    nested.insert("b", Unsized(vec![Entries(vec![("zz", 1), ("y", 2)])]));
+    nested.insert("aa", Unsized(vec![]));
+    nested.insert("a", Unsized(vec![Entries(vec![])]));
+    check(
+        &Entries(vec![("x", nested), ("", BTreeMap::new())]),
+        options,
+    );
+
+    #[derive(Serialize)]
+    enum Value {
+        Struct { b: u8, a: Vec<(u8, String)> },
+        Tuple(u8, BTreeMap<char, u8>),
+    }
+    let mut chars = BTreeMap::new();
+    chars.insert('b', 1);
+    chars.insert('a', 2);
+    check(
+        &vec![
+            Value::Struct {
+                b: 1,
+                a: vec![(2, "c".into())],
+            },
+            Value::Tuple(3, chars),
+        ],
+        options,
+    );
+
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let mut ipld = BTreeMap::new();
+    ipld.insert("link".to_string(), Ipld::Link(cid));
+    ipld.insert("float".to_string(), Ipld::Float(0.5));
+    ipld.insert(
+        "list".to_string(),
+        Ipld::List(vec![Ipld::Null, Ipld::Bool(true)]),
+    );
+    check(&Ipld::Map(ipld), options);
+
+    // Integer keys are replaced in place.
+    let ints = Entries(vec![(-1i64, 1), (i64::MIN, 2), (1000, 3), (23, 4), (0, 5)]);
+    check(&ints, options.stringify_integer_keys(true));
+    let big = Entries(vec![(u64::MAX, 1), (0, 2)]);
+    check(&big, options.stringify_integer_keys(true));
+}
+
+#[test]
+fn test_to_slice_errors() {
+    let mut buf = [0; 64];
+    let duplicates = Entries(vec![("b", 1), ("a", 2), ("b", 3)]);
+    let err = ser::to_slice(&mut buf, &vec![duplicates]).unwrap_err();
+    assert!(matches!(&err, EncodeError::DuplicateMapKey { key, .. } if key == "b"));
+    assert_eq!(err.path().to_string(), "[0]");
+    let options = SerializerOptions::new().check_duplicate_keys(false);
+    let duplicates = Entries(vec![("b", 1), ("a", 2), ("b", 3)]);
+    let len = ser::to_slice_with(&mut buf, &duplicates, options).unwrap();
+    assert_eq!(&buf[..len], b"\xa3\x61a\x02\x61b\x01\x61b\x03");
+
+    let mut map = BTreeMap::new();
+    map.insert("b", Entries(vec![("c", f64::NAN)]));
+    let err = ser::to_slice(&mut buf, &map).unwrap_err();
+    assert_eq!(err.path().to_string(), "b.c");
+
+    let err = ser::to_slice(&mut buf, &Entries(vec![(1, 1)])).unwrap_err();
+    assert_eq!(err.to_string(), "Map keys must be strings");
+
+    let options = SerializerOptions::new().max_output_size(4);
+    let err = ser::to_slice_with(&mut buf, &"too long", options).unwrap_err();
+    assert!(matches!(err, EncodeError::OutputTooLarge { limit: 4, .. }));
+
+    // The value of a map entry doesn't fit.
+    let map = BTreeMap::from([("k", ByteBuf::from(vec![0; 40]))]);
+    let err = ser::to_slice(&mut [0; 10], &map).unwrap_err();
+    assert!(matches!(err, EncodeError::Write(BufferFull)));
+}
+
+/// A map that is serialized with the entries in the given order.
+struct Entries<K, V>(Vec<(K, V)>);
+
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for Entries<K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+    }
+}
+
+/// Returns the entries of a map in reverse order.
+fn reversed<K: Clone, V: Clone>(map: &BTreeMap<K, V>) -> Entries<K, V> {
+    Entries(
+        map.iter()
+            .rev()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_to_vec_into() {
+    let mut map = BTreeMap::new();
+    map.insert("bb", Unsized(vec![1u8, 2]));
+    map.insert("a", Unsized(vec![]));
+    let encoded = to_vec(&map).unwrap();
+
+    let mut buf = b"prefix".to_vec();
+    ser::to_vec_into(&mut buf, &map).unwrap();
+    assert_eq!(&buf[..6], b"prefix");
+    assert_eq!(&buf[6..], &encoded[..]);
+
+    // Nothing is appended on errors.
+    let err = ser::to_vec_into(&mut buf, &vec![1.0, f64::NAN]).unwrap_err();
+    assert_eq!(err.path().to_string(), "[1]");
+    assert_eq!(&buf[6..], &encoded[..]);
+}
+
+#[test]
+fn test_encoder_reuse() {
+    let options = SerializerOptions::new().max_depth(3);
+    let mut encoder = ser::Encoder::with_options(options);
+    assert_eq!(encoder.options(), options);
+
+    let mut map = BTreeMap::new();
+    map.insert("bb", Unsized(vec![Unsized(vec![1u8, 2])]));
+    map.insert("a", Unsized(vec![]));
+    let encoded = to_vec(&map).unwrap();
+
+    let mut buf = Vec::new();
+    for _ in 0..3 {
+        encoder.encode_into(&mut buf, &map).unwrap();
+        // Errors within buffered containers don't affect the next value.
+        let mut failing = BTreeMap::new();
+        failing.insert("a", Unsized(vec![Unsized(vec![Unsized(vec![1])])]));
+        let err = encoder.encode_into(&mut buf, &failing).unwrap_err();
+        assert!(matches!(err, EncodeError::DepthOverflow { limit: 3, .. }));
+        assert_eq!(err.path().to_string(), "a[0][0]");
+    }
+    assert_eq!(buf, encoded.repeat(3));
+    assert_eq!(encoder.to_vec(&map).unwrap(), encoded);
+}
@@ -1,6 +1,7 @@
 use serde_derive::{Deserialize, Serialize};
 
-use serde_ipld_dagcbor::{from_slice, to_vec};
+use ipld_core::ipld::Ipld;
+use serde_ipld_dagcbor::{de, from_slice, to_vec, DecodeError};
 
 fn to_binary(s: &'static str) -> Vec<u8> {
     assert!(s.len().is_multiple_of(2));
@@ -204,6 +205,32 @@ fn test_accept_f32_cbor_marker_for_compatibility() {
     assert_eq!(result.unwrap(), 1.5f32);
 }
 
+#[test]
+fn test_reject_f32_cbor_marker_in_strict_mode() {
+    // 1.5 in IEEE 754 single precision.
+    let f32_cbor = [0xfa, 0x3f, 0xc0, 0x00, 0x00];
+    let result: Result<f32, _> = de::from_slice_strict(&f32_cbor);
+    assert!(matches!(
+        result.unwrap_err(),
+        DecodeError::Mismatch { found: 0xfa, .. }
+    ));
+    let result: Result<Ipld, _> = de::from_slice_strict(&f32_cbor);
+    assert!(matches!(
+        result.unwrap_err(),
+        DecodeError::Mismatch { found: 0xfa, .. }
+    ));
+
+    // Only this check can be enabled as well.
+    let options = de::DeserializerOptions::new().require_f64_floats(true);
+    let result: Result<f32, _> = de::from_slice_with(&f32_cbor, options);
+    assert!(result.is_err());
+
+    // 1.5 in IEEE 754 double precision is still accepted as f32.
+    let f64_cbor = [0xfb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    let result: f32 = de::from_slice_strict(&f64_cbor).unwrap();
+    assert_eq!(result, 1.5f32);
+}
+
 #[test]
 fn test_f32_strict_precision_rejection() {
     // Test that f64 values with more precision than f32 can represent are rejected
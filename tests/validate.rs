@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use ipld_core::{cid::Cid, ipld::Ipld};
+use serde_ipld_dagcbor::{
+    error::InvalidCidReason,
+    to_vec,
+    validate::{validate, ValidationReport},
+    DecodeError,
+};
+
+#[test]
+fn test_validate_valid() {
+    let cid = Cid::from_str("bafkreibme22gw2h7y2h7tg2fhqotaqjucnbc24deqo72b6mkl2egezxhvy").unwrap();
+    let mut map = BTreeMap::new();
+    map.insert("link".to_string(), Ipld::Link(cid));
+    map.insert(
+        "list".to_string(),
+        Ipld::List(vec![
+            Ipld::Integer(-1),
+            Ipld::Float(1.5),
+            Ipld::String("string".to_string()),
+            Ipld::Bytes(vec![0x01, 0x02]),
+            Ipld::Bool(true),
+            Ipld::Null,
+        ]),
+    );
+    let encoded = to_vec(&Ipld::Map(map)).unwrap();
+
+    let report = validate(&encoded).unwrap();
+    assert_eq!(
+        report,
+        ValidationReport {
+            values: 9,
+            links: 1,
+            max_depth: 3,
+            len: encoded.len(),
+        }
+    );
+}
+
+#[test]
+fn test_validate_violations() {
+    let cases: &[(&[u8], usize)] = &[
+        // Non-minimal integer.
+        (&[0x82, 0x01, 0x18, 0x05], 2),
+        // Unsorted map keys {"b": 1, "a": 2}.
+        (&[0xa2, 0x61, 0x62, 0x01, 0x61, 0x61, 0x02], 4),
+        // Indefinite length list.
+        (&[0x9f, 0x01, 0xff], 0),
+        // Map key that is a string of indefinite length.
+        (b"\xa1\x7f\x61a\xff\x01", 1),
+        // Tag other than 42.
+        (&[0xd8, 0x28, 0x42, 0x00, 0x01], 0),
+        // f32 float.
+        (&[0xfa, 0x3f, 0xc0, 0x00, 0x00], 0),
+        // Undefined.
+        (&[0xf7], 0),
+        // Trailing data.
+        (&[0x01, 0x02], 1),
+    ];
+    for (input, offset) in cases {
+        let error = validate(input).unwrap_err();
+        assert_eq!(error.offset, *offset, "for input {:02x?}", input);
+    }
+}
+
+#[test]
+fn test_validate_invalid_cid() {
+    // Tag 42 with bytes that aren't a CID.
+    let error = validate(&[0xd8, 0x2a, 0x43, 0x00, 0x01, 0x02]).unwrap_err();
+    assert!(matches!(
+        error.error,
+        DecodeError::InvalidCid {
+            reason: InvalidCidReason::Truncated,
+            ..
+        }
+    ));
+    assert_eq!(error.offset, 6);
+}
+
+#[test]
+fn test_validate_non_finite_float() {
+    let error = validate(b"\xfb\x7f\xf8\x00\x00\x00\x00\x00\x00").unwrap_err();
+    assert!(matches!(error.error, DecodeError::Mismatch { .. }));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_validate_reader() {
+    use serde_ipld_dagcbor::validate::validate_reader;
+
+    let report = validate_reader(&b"\x82\x01\x02"[..]).unwrap();
+    assert_eq!(report.values, 3);
+    assert_eq!(report.len, 3);
+
+    let error = validate_reader(&b"\x82\x01\x18\x05"[..]).unwrap_err();
+    assert!(matches!(
+        error.error,
+        DecodeError::NonMinimalEncoding { .. }
+    ));
+}